@@ -0,0 +1,108 @@
+/*
+    Compares the default SipHash against FxHash (and DenseMemo
+    where the key is plain indices) for the key shapes the
+    solutions actually memoize on.
+    Run with `cargo bench --bench hashing`
+*/
+
+use std::hash::{BuildHasher, RandomState};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use advent_of_code_2025::fx_hash::FxBuildHasher;
+use advent_of_code_2025::memoizer::{DenseMemo, HashRef, Memoizer};
+
+const ITERATIONS: u32 = 20;
+const NUM_KEYS: u64 = 100_000;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn tuple_keys<S: BuildHasher + Default>() -> Duration {
+    time(|| {
+        let mut memo = Memoizer::<(u64, u8), u64, S>::new();
+        for i in 0..NUM_KEYS {
+            memo.insert((i, (i % 12) as u8), i);
+        }
+        for i in 0..NUM_KEYS {
+            black_box(memo.get(&(i, (i % 12) as u8)));
+        }
+    })
+}
+
+fn slice_ref_keys<S: BuildHasher + Default>(digits: &[u8]) -> Duration {
+    time(|| {
+        let mut memo = Memoizer::<(HashRef<[u8]>, u8), u64, S>::new();
+        for start in 0..digits.len() {
+            for num_digits in 1..=12 {
+                memo.insert(
+                    (
+                        HashRef {
+                            _ref: &digits[start..],
+                        },
+                        num_digits,
+                    ),
+                    0,
+                );
+            }
+        }
+        for start in 0..digits.len() {
+            for num_digits in 1..=12 {
+                black_box(memo.get(&(
+                    HashRef {
+                        _ref: &digits[start..],
+                    },
+                    num_digits,
+                )));
+            }
+        }
+    })
+}
+
+fn dense_keys(digits: &[u8]) -> Duration {
+    time(|| {
+        let mut memo = DenseMemo::<u64, 2>::new([digits.len() + 1, 13]);
+        for start in 0..digits.len() {
+            for num_digits in 1..=12 {
+                memo.insert([digits.len() - start, num_digits], 0);
+            }
+        }
+        for start in 0..digits.len() {
+            for num_digits in 1..=12 {
+                black_box(memo.get([digits.len() - start, num_digits]));
+            }
+        }
+    })
+}
+
+fn report(name: &str, sip: Duration, fx: Duration) {
+    println!(
+        "{name:<16} SipHash: {sip:>12?}  FxHash: {fx:>12?}  speedup: {:.2}x",
+        sip.as_secs_f64() / fx.as_secs_f64()
+    );
+}
+
+fn main() {
+    report(
+        "(u64, u8)",
+        tuple_keys::<RandomState>(),
+        tuple_keys::<FxBuildHasher>(),
+    );
+
+    let digits: Vec<u8> = (0..10_000).map(|i| (i * 7 % 10) as u8).collect();
+    report(
+        "(HashRef, u8)",
+        slice_ref_keys::<RandomState>(&digits),
+        slice_ref_keys::<FxBuildHasher>(&digits),
+    );
+    println!(
+        "{:<16} DenseMemo: {:>10?}",
+        "[usize; 2]",
+        dense_keys(&digits)
+    );
+}
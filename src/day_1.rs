@@ -1,34 +1,403 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Dial that lines without a `name:` prefix turn
+pub const DEFAULT_DIAL: &str = "default";
+
 pub struct Day1;
 
 impl crate::Day for Day1 {
     fn run(input: String) -> crate::DayResult {
-        let mut dial = 50;
-        let mut hit_0 = 0;
-        let mut total = 0;
+        let mut lock = CombinationLock::new(Dial::default());
 
         for line in input.lines() {
-            let (direction, value_str) = line.split_at(1);
-            let change: i32 = value_str.trim().parse().expect("Failed to parse value");
-
-            if direction == "L" {
-                if dial == 0 {
-                    dial = 100;
-                }
-                total += (dial - change - 100) / (-100);
-                dial -= change;
-            } else if direction == "R" {
-                total += (dial + change) / 100;
-                dial += change;
-            }
-            dial = ((dial % 100) + 100) % 100; // keep dial_val in [0, 99]
-            if dial == 0 {
-                hit_0 += 1;
-            }
+            let instruction = line
+                .parse::<DialInstruction>()
+                .unwrap_or_else(|err| panic!("Failed to parse rotation {line:?}: {err}"));
+            lock.apply(&instruction);
         }
 
+        let total = lock.total();
         crate::DayResult {
-            part_1: hit_0,
-            part_2: total as u64,
+            part_1: total.hits,
+            part_2: total.crossings,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    pub direction: Direction,
+    pub distance: u64,
+}
+
+/// A rotation addressed to a named dial, e.g. `A:L30`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialInstruction {
+    pub dial: String,
+    pub rotation: Rotation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RotationParseError {
+    Empty,
+    EmptyDialName,
+    UnknownDirection(char),
+    InvalidDistance(String),
+}
+
+impl fmt::Display for RotationParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RotationParseError::Empty => write!(f, "empty rotation"),
+            RotationParseError::EmptyDialName => write!(f, "empty dial name before ':'"),
+            RotationParseError::UnknownDirection(c) => {
+                write!(f, "unknown direction {c:?}, expected 'L' or 'R'")
+            }
+            RotationParseError::InvalidDistance(s) => write!(f, "invalid distance {s:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RotationParseError {}
+
+impl FromStr for Rotation {
+    type Err = RotationParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        let direction = match chars.next() {
+            None => return Err(RotationParseError::Empty),
+            Some('L') => Direction::Left,
+            Some('R') => Direction::Right,
+            Some(c) => return Err(RotationParseError::UnknownDirection(c)),
+        };
+
+        let distance_str = chars.as_str().trim();
+        let distance = distance_str
+            .parse()
+            .map_err(|_| RotationParseError::InvalidDistance(distance_str.to_string()))?;
+
+        Ok(Rotation {
+            direction,
+            distance,
+        })
+    }
+}
+
+impl FromStr for DialInstruction {
+    type Err = RotationParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (dial, rotation) = match s.split_once(':') {
+            Some((dial, rotation)) => (dial.trim(), rotation),
+            None => (DEFAULT_DIAL, s),
+        };
+        if dial.is_empty() {
+            return Err(RotationParseError::EmptyDialName);
+        }
+
+        Ok(DialInstruction {
+            dial: dial.to_string(),
+            rotation: rotation.parse()?,
+        })
+    }
+}
+
+/// A circular dial with positions 0..size.
+/// Counts how often the pointer lands on or sweeps past the target
+#[derive(Debug, Clone)]
+pub struct Dial {
+    size: u64,
+    position: u64,
+    target: u64,
+}
+
+impl Default for Dial {
+    fn default() -> Self {
+        Dial::new(100, 50, 0)
+    }
+}
+
+impl Dial {
+    pub fn new(size: u64, start: u64, target: u64) -> Self {
+        assert!(size > 0, "Dial must have at least one position");
+        assert!(
+            start < size,
+            "Start position {start} is off a dial of size {size}"
+        );
+        assert!(
+            target < size,
+            "Target {target} is off a dial of size {size}"
+        );
+
+        Dial {
+            size,
+            position: start,
+            target,
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn target(&self) -> u64 {
+        self.target
+    }
+
+    /// Turns the dial, returning how many times the pointer reached the
+    /// target along the way (including where it stops, but not where it started)
+    pub fn rotate(&mut self, rotation: Rotation) -> u64 {
+        let steps = rotation.distance % self.size;
+
+        // Clicks until the pointer first reaches the target, a full turn if it's already there
+        let first_hit = match rotation.direction {
+            Direction::Left => (self.position + self.size - self.target) % self.size,
+            Direction::Right => (self.target + self.size - self.position) % self.size,
+        };
+        let first_hit = if first_hit == 0 { self.size } else { first_hit };
+
+        self.position = match rotation.direction {
+            Direction::Left => (self.position + self.size - steps) % self.size,
+            Direction::Right => (self.position + steps) % self.size,
+        };
+
+        if rotation.distance < first_hit {
+            0
+        } else {
+            1 + (rotation.distance - first_hit) / self.size
         }
     }
+
+    /// Applies each rotation in turn, yielding (position, crossings) after each one
+    pub fn trace<'a, I>(&'a mut self, rotations: I) -> impl Iterator<Item = (u64, u64)> + 'a
+    where
+        I: IntoIterator<Item = Rotation>,
+        I::IntoIter: 'a,
+    {
+        rotations.into_iter().map(move |rotation| {
+            let crossings = self.rotate(rotation);
+            (self.position, crossings)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DialStats {
+    /// Rotations that stopped on the target
+    pub hits: u64,
+    /// Times the pointer reached the target, mid-rotation or not
+    pub crossings: u64,
+}
+
+/// Set of independently turning dials, created on first use as
+/// copies of a template dial
+#[derive(Debug, Clone)]
+pub struct CombinationLock {
+    template: Dial,
+    dials: BTreeMap<String, (Dial, DialStats)>,
+}
+
+impl CombinationLock {
+    pub fn new(template: Dial) -> Self {
+        CombinationLock {
+            template,
+            dials: BTreeMap::new(),
+        }
+    }
+
+    pub fn apply(&mut self, instruction: &DialInstruction) {
+        let (dial, stats) = self
+            .dials
+            .entry(instruction.dial.clone())
+            .or_insert_with(|| (self.template.clone(), DialStats::default()));
+
+        stats.crossings += dial.rotate(instruction.rotation);
+        if dial.position() == dial.target() {
+            stats.hits += 1;
+        }
+    }
+
+    pub fn dial(&self, name: &str) -> Option<&Dial> {
+        self.dials.get(name).map(|(dial, _)| dial)
+    }
+
+    /// Per-dial stats, in name order
+    pub fn stats(&self) -> impl Iterator<Item = (&str, DialStats)> {
+        self.dials
+            .iter()
+            .map(|(name, (_, stats))| (name.as_str(), *stats))
+    }
+
+    pub fn total(&self) -> DialStats {
+        self.stats()
+            .fold(DialStats::default(), |acc, (_, stats)| DialStats {
+                hits: acc.hits + stats.hits,
+                crossings: acc.crossings + stats.crossings,
+            })
+    }
+}
+
+impl fmt::Display for CombinationLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, stats) in self.stats() {
+            writeln!(
+                f,
+                "{name}: {} hits, {} crossings",
+                stats.hits, stats.crossings
+            )?;
+        }
+        let total = self.total();
+        write!(
+            f,
+            "total: {} hits, {} crossings",
+            total.hits, total.crossings
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Day;
+
+    const EXAMPLE: &str = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82\n";
+
+    fn rotation(s: &str) -> Rotation {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn example() {
+        let result = Day1::run(EXAMPLE.to_string());
+        assert_eq!(result.part_1, 3);
+        assert_eq!(result.part_2, 6);
+    }
+
+    #[test]
+    fn landing_on_the_target() {
+        let mut dial = Dial::default();
+        assert_eq!(dial.rotate(rotation("R50")), 1);
+        assert_eq!(dial.position(), 0);
+
+        let mut dial = Dial::default();
+        assert_eq!(dial.rotate(rotation("L50")), 1);
+        assert_eq!(dial.position(), 0);
+    }
+
+    #[test]
+    fn starting_on_the_target() {
+        let mut dial = Dial::new(100, 0, 0);
+        assert_eq!(dial.rotate(rotation("R5")), 0);
+        assert_eq!(dial.rotate(rotation("L5")), 1);
+        assert_eq!(dial.rotate(rotation("L99")), 0);
+        assert_eq!(dial.rotate(rotation("R0")), 0);
+        assert_eq!(dial.position(), 1);
+
+        let mut dial = Dial::new(100, 0, 0);
+        assert_eq!(dial.rotate(rotation("L100")), 1);
+        assert_eq!(dial.position(), 0);
+    }
+
+    #[test]
+    fn whole_turns() {
+        let mut dial = Dial::default();
+        assert_eq!(dial.rotate(rotation("R200")), 2);
+        assert_eq!(dial.position(), 50);
+        assert_eq!(dial.rotate(rotation("L250")), 3);
+        assert_eq!(dial.position(), 0);
+        assert_eq!(dial.rotate(rotation("R300")), 3);
+        assert_eq!(dial.position(), 0);
+    }
+
+    #[test]
+    fn custom_dial() {
+        let mut dial = Dial::new(10, 3, 7);
+        let trace: Vec<_> = dial
+            .trace(["R4", "L15", "R25", "L0"].map(rotation))
+            .collect();
+        assert_eq!(trace, [(7, 1), (2, 1), (7, 3), (7, 0)]);
+    }
+
+    #[test]
+    fn named_dials() {
+        let mut lock = CombinationLock::new(Dial::default());
+        for line in ["A:R50", "B:L20", "A:L100", "B:R70", "R10", " A :R1"] {
+            lock.apply(&line.parse().unwrap());
+        }
+
+        let stats: Vec<_> = lock.stats().collect();
+        assert_eq!(
+            stats,
+            [
+                (
+                    "A",
+                    DialStats {
+                        hits: 2,
+                        crossings: 2
+                    }
+                ),
+                (
+                    "B",
+                    DialStats {
+                        hits: 1,
+                        crossings: 1
+                    }
+                ),
+                (DEFAULT_DIAL, DialStats::default()),
+            ]
+        );
+        assert_eq!(
+            lock.total(),
+            DialStats {
+                hits: 3,
+                crossings: 3
+            }
+        );
+        assert_eq!(lock.dial("A").map(Dial::position), Some(1));
+        assert_eq!(lock.dial("B").map(Dial::position), Some(0));
+        assert_eq!(lock.dial(DEFAULT_DIAL).map(Dial::position), Some(60));
+        assert!(lock.dial("C").is_none());
+    }
+
+    #[test]
+    fn empty_dial_name() {
+        assert_eq!(
+            ":R5".parse::<DialInstruction>(),
+            Err(RotationParseError::EmptyDialName)
+        );
+        assert_eq!(
+            "  :R5".parse::<DialInstruction>(),
+            Err(RotationParseError::EmptyDialName)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "L 30".parse::<Rotation>(),
+            Ok(Rotation {
+                direction: Direction::Left,
+                distance: 30
+            })
+        );
+        assert_eq!(
+            "X30".parse::<Rotation>(),
+            Err(RotationParseError::UnknownDirection('X'))
+        );
+        assert_eq!("  ".parse::<Rotation>(), Err(RotationParseError::Empty));
+        assert_eq!(
+            "R-3".parse::<Rotation>(),
+            Err(RotationParseError::InvalidDistance("-3".to_string()))
+        );
+    }
 }
@@ -1,9 +1,13 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::BTreeMap;
+
+use crate::fx_hash::{FxBuildHasher, FxHashMap, FxHashSet};
+use crate::memoizer::{Memoizer, hash_input, memo_path};
 
 pub struct Day11;
 
 impl crate::Day for Day11 {
     fn run(input: String) -> crate::DayResult {
+        let input_hash = hash_input(&input);
         let edges = input
             .lines()
             .map(|line| {
@@ -21,8 +25,21 @@ impl crate::Day for Day11 {
 
         let graph = DiGraph::from_edges(&edges);
 
+        // Path counts only depend on the graph, so a previous run on the
+        // same input can hand them straight back
+        let memo_path = memo_path(11, "paths_to_out");
+        let mut paths_to_out =
+            Memoizer::load_or_new(&memo_path, input_hash).unwrap_or_else(|err| {
+                eprintln!("Ignoring saved Day 11 path counts: {err}");
+                Memoizer::default()
+            });
+        let part_1 = graph.num_paths("you", "out", &mut paths_to_out);
+        if let Err(err) = paths_to_out.save(&memo_path, input_hash) {
+            eprintln!("Failed to save Day 11 path counts: {err}");
+        }
+
         crate::DayResult {
-            part_1: graph.num_paths("you", "out"),
+            part_1,
             part_2: graph.num_paths_visit_vertices("svr", "out"),
         }
     }
@@ -30,12 +47,12 @@ impl crate::Day for Day11 {
 
 #[derive(Debug)]
 struct DiGraph<'a> {
-    vertices: HashMap<&'a str, Vec<&'a str>>,
+    vertices: FxHashMap<&'a str, Vec<&'a str>>,
 }
 
 impl<'a> DiGraph<'a> {
     pub fn from_edges(edges: &[(&'a str, &'a str)]) -> Self {
-        let mut vertices: HashMap<&str, Vec<&'a str>> = HashMap::new();
+        let mut vertices: FxHashMap<&str, Vec<&'a str>> = FxHashMap::default();
 
         for &(src, dst) in edges {
             vertices.entry(src).or_default().push(dst);
@@ -45,21 +62,29 @@ impl<'a> DiGraph<'a> {
         DiGraph { vertices }
     }
 
-    pub fn num_paths(&self, src_vertex: &'a str, dst_vertex: &'a str) -> u64 {
-        let mut num_paths = 0;
-        let mut to_visit = vec![src_vertex];
-
-        while let Some(current_vertex) = to_visit.pop() {
-            if current_vertex == dst_vertex {
-                num_paths += 1;
-                continue;
-            }
-
-            for &next_vertex in self.vertices.get(current_vertex).unwrap().iter() {
-                to_visit.push(next_vertex);
-            }
+    /// Counts paths from src_vertex to dst_vertex. `memo` maps vertex names
+    /// to their path counts, so it has to be kept to one dst_vertex
+    pub fn num_paths(
+        &self,
+        src_vertex: &'a str,
+        dst_vertex: &'a str,
+        memo: &mut Memoizer<String, u64, FxBuildHasher>,
+    ) -> u64 {
+        if src_vertex == dst_vertex {
+            return 1;
+        }
+        if let Some(&num_paths) = memo.get(src_vertex) {
+            return num_paths;
         }
 
+        let num_paths = self
+            .vertices
+            .get(src_vertex)
+            .unwrap()
+            .iter()
+            .map(|&next_vertex| self.num_paths(next_vertex, dst_vertex, memo))
+            .sum();
+        memo.insert(src_vertex.to_string(), num_paths);
         num_paths
     }
 
@@ -109,8 +134,8 @@ impl<'a> DiGraph<'a> {
         unreachable!()
     }
 
-    fn get_reachable(&self, src_vertex: &'a str) -> HashSet<&'a str> {
-        let mut reachable = HashSet::new();
+    fn get_reachable(&self, src_vertex: &'a str) -> FxHashSet<&'a str> {
+        let mut reachable = FxHashSet::default();
 
         let mut to_visit = vec![src_vertex];
 
@@ -129,9 +154,9 @@ impl<'a> DiGraph<'a> {
 
     fn calculate_in_degrees(
         &self,
-        reachable: &HashSet<&'a str>,
-    ) -> HashMap<&'a str, (Vec<&'a str>, u64)> {
-        let mut vertices = HashMap::new();
+        reachable: &FxHashSet<&'a str>,
+    ) -> FxHashMap<&'a str, (Vec<&'a str>, u64)> {
+        let mut vertices = FxHashMap::default();
 
         for &vertex in reachable {
             vertices.entry(vertex).or_insert((Vec::new(), 0));
@@ -187,3 +212,48 @@ impl PartialOrd for SearchEntry<'_> {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[(&str, &str)] = &[
+        ("aaa", "you"),
+        ("aaa", "hhh"),
+        ("you", "bbb"),
+        ("you", "ccc"),
+        ("bbb", "ddd"),
+        ("bbb", "eee"),
+        ("ccc", "ddd"),
+        ("ccc", "eee"),
+        ("ccc", "fff"),
+        ("ddd", "ggg"),
+        ("eee", "out"),
+        ("fff", "out"),
+        ("ggg", "out"),
+        ("hhh", "ccc"),
+        ("hhh", "fff"),
+        ("hhh", "iii"),
+        ("iii", "out"),
+    ];
+
+    #[test]
+    fn warm_start_from_saved_counts() {
+        let graph = DiGraph::from_edges(EXAMPLE);
+        let mut memo = Memoizer::default();
+        assert_eq!(graph.num_paths("you", "out", &mut memo), 5);
+        assert_eq!(memo.get("ccc"), Some(&3));
+
+        let dir = std::env::temp_dir().join(format!("day_11_test_{}", std::process::id()));
+        let path = dir.join("paths_to_out.memo");
+        memo.save(&path, 7).unwrap();
+        let mut loaded = Memoizer::load_or_new(&path, 7).unwrap();
+        assert_eq!(loaded.get("you"), Some(&5));
+        assert_eq!(graph.num_paths("aaa", "out", &mut loaded), 10);
+
+        // A table saved for another input starts over
+        let fresh: Memoizer<String, u64, FxBuildHasher> = Memoizer::load_or_new(&path, 8).unwrap();
+        assert_eq!(fresh.get("you"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
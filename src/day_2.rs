@@ -1,13 +1,31 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
 pub struct Day2;
 
 impl crate::Day for Day2 {
     fn run(input: String) -> crate::DayResult {
-        let id_ranges = input.split(",");
+        let id_ranges = parse_ranges(&input);
 
-        let mut id_sum_part_1 = 0;
-        let mut id_sum_part_2 = 0;
+        let total = |rule: RepetitionRule| {
+            let total: u128 = id_ranges
+                .iter()
+                .map(|range| rule.invalid_id_sum(range))
+                .sum();
+            u64::try_from(total).expect("Invalid ID total doesn't fit in a u64")
+        };
 
-        for range in id_ranges {
+        crate::DayResult {
+            part_1: total(RepetitionRule::exactly(2)),
+            part_2: total(RepetitionRule::at_least(2)),
+        }
+    }
+}
+
+pub fn parse_ranges(input: &str) -> Vec<RangeInclusive<u64>> {
+    input
+        .split(",")
+        .map(|range| {
             let (start, end) = range
                 .trim()
                 .split_once("-")
@@ -15,51 +33,341 @@ impl crate::Day for Day2 {
 
             let start_id: u64 = start.parse().expect("Failed to parse start ID");
             let end_id: u64 = end.parse().expect("Failed to parse end ID");
+            start_id..=end_id
+        })
+        .collect()
+}
 
-            for id in start_id..=end_id {
-                if is_invalid_id_part_1(id.to_string()) {
-                    id_sum_part_1 += id;
-                }
-                if is_invalid_id_part_2(id.to_string()) {
-                    id_sum_part_2 += id;
+/// Which IDs count as invalid: those whose digits (in `base`) are one
+/// block repeated between `min_repeats` and `max_repeats` times.
+/// A block always has to repeat at least twice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepetitionRule {
+    min_repeats: u32,
+    max_repeats: Option<u32>,
+    base: u32,
+}
+
+impl RepetitionRule {
+    pub fn exactly(repeats: u32) -> Self {
+        Self::between(repeats, repeats)
+    }
+
+    pub fn at_least(min_repeats: u32) -> Self {
+        assert!(min_repeats >= 2, "A block must repeat at least twice");
+        RepetitionRule {
+            min_repeats,
+            max_repeats: None,
+            base: 10,
+        }
+    }
+
+    pub fn at_most(max_repeats: u32) -> Self {
+        Self::between(2, max_repeats)
+    }
+
+    pub fn between(min_repeats: u32, max_repeats: u32) -> Self {
+        assert!(min_repeats <= max_repeats, "Empty repeat range");
+        RepetitionRule {
+            max_repeats: Some(max_repeats),
+            ..Self::at_least(min_repeats)
+        }
+    }
+
+    pub fn in_base(self, base: u32) -> Self {
+        assert!((2..=36).contains(&base), "Unsupported base {base}");
+        RepetitionRule { base, ..self }
+    }
+
+    pub fn min_repeats(&self) -> u32 {
+        self.min_repeats
+    }
+
+    /// None if there's no limit
+    pub fn max_repeats(&self) -> Option<u32> {
+        self.max_repeats
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub fn allows(&self, repeats: u32) -> bool {
+        repeats >= self.min_repeats && self.max_repeats.is_none_or(|max| repeats <= max)
+    }
+
+    // All invalid IDs in the range, sorted and de-duplicated, since e.g. 222222
+    // is "2" six times, "22" three times and "222" twice.
+    //
+    // An ID of D digits with a block of length L repeated k = D / L times is
+    // block * (b^D - 1) / (b^L - 1), so the IDs in range come straight from
+    // the range of blocks rather than checking every integer.
+    pub fn invalid_ids(&self, range: &RangeInclusive<u64>) -> Vec<u64> {
+        let mut ids = Vec::new();
+        for num_digits in self.digit_counts(range) {
+            for block_length in 1..num_digits {
+                let repeats = num_digits / block_length;
+                if !num_digits.is_multiple_of(block_length) || !self.allows(repeats) {
+                    continue;
                 }
+
+                let (multiplier, blocks) = self.blocks(range, num_digits, block_length);
+                ids.extend(blocks.map(|block| (block * multiplier) as u64));
             }
         }
 
-        crate::DayResult {
-            part_1: id_sum_part_1,
-            part_2: id_sum_part_2,
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    // Sum of invalid_ids(range) without listing them. The IDs made of
+    // length L blocks sum to multiplier * (the blocks' arithmetic series).
+    // Digits with a repeating block of length L also repeat with the
+    // shortest such block, whose length divides L, so each ID is counted
+    // once by splitting the sums up by that shortest length:
+    //   primitive(d) = periodic(d) - sum of primitive(e) for e dividing d
+    // and adding primitive(d) for every d dividing an allowed block length.
+    pub fn invalid_id_sum(&self, range: &RangeInclusive<u64>) -> u128 {
+        let mut total = 0;
+        for num_digits in self.digit_counts(range) {
+            let block_lengths: Vec<u32> = (1..num_digits)
+                .filter(|&length| num_digits.is_multiple_of(length))
+                .collect();
+
+            // In increasing order, so every divisor is done before its multiples
+            let mut primitive_sums: Vec<(u32, u128)> = Vec::new();
+            for &length in &block_lengths {
+                let (multiplier, blocks) = self.blocks(range, num_digits, length);
+                let periodic_sum = multiplier * arithmetic_series(blocks);
+                let shorter_sum: u128 = primitive_sums
+                    .iter()
+                    .filter(|(shorter, _)| length.is_multiple_of(*shorter))
+                    .map(|(_, sum)| sum)
+                    .sum();
+                primitive_sums.push((length, periodic_sum - shorter_sum));
+            }
+
+            total += primitive_sums
+                .iter()
+                .filter(|(primitive, _)| {
+                    block_lengths.iter().any(|&length| {
+                        length.is_multiple_of(*primitive) && self.allows(num_digits / length)
+                    })
+                })
+                .map(|(_, sum)| sum)
+                .sum::<u128>();
         }
+        total
     }
+
+    // Digit counts of the IDs in range, none for an empty range
+    fn digit_counts(&self, range: &RangeInclusive<u64>) -> RangeInclusive<u32> {
+        if range.is_empty() {
+            return RangeInclusive::new(1, 0);
+        }
+        self.num_digits(*range.start())..=self.num_digits(*range.end())
+    }
+
+    // The multiplier that repeats a block_length block out to num_digits,
+    // and the blocks whose repeated IDs fall in range
+    fn blocks(
+        &self,
+        range: &RangeInclusive<u64>,
+        num_digits: u32,
+        block_length: u32,
+    ) -> (u128, RangeInclusive<u128>) {
+        let base = self.base as u128;
+        let multiplier = (base.pow(num_digits) - 1) / (base.pow(block_length) - 1);
+        let min_block = u128::max(
+            base.pow(block_length - 1),
+            (*range.start() as u128).div_ceil(multiplier),
+        );
+        let max_block = u128::min(
+            base.pow(block_length) - 1,
+            *range.end() as u128 / multiplier,
+        );
+        (multiplier, min_block..=max_block)
+    }
+
+    fn num_digits(&self, n: u64) -> u32 {
+        n.checked_ilog(self.base as u64).unwrap_or(0) + 1
+    }
+}
+
+// Sum of every integer in the range
+fn arithmetic_series(range: RangeInclusive<u128>) -> u128 {
+    if range.is_empty() {
+        return 0;
+    }
+    let (first, last) = range.into_inner();
+    // One of the two factors is even
+    (first + last) * (last - first + 1) / 2
 }
 
-fn is_invalid_id_part_1(id: String) -> bool {
-    if !id.len().is_multiple_of(2) {
-        return false;
+/// The invalid IDs found in one input range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeReport {
+    pub range: RangeInclusive<u64>,
+    pub invalid_ids: Vec<u64>,
+}
+
+impl RangeReport {
+    pub fn sum(&self) -> u128 {
+        self.invalid_ids.iter().map(|&id| id as u128).sum()
     }
 
-    let (first_half, second_half) = id.split_at(id.len() / 2);
-    first_half == second_half
+    pub fn count(&self) -> usize {
+        self.invalid_ids.len()
+    }
 }
 
-fn is_invalid_id_part_2(id: String) -> bool {
-    for factor in 2..=id.len() {
-        if !id.len().is_multiple_of(factor) {
-            continue;
+impl fmt::Display for RangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}: {} invalid, sum {}",
+            self.range.start(),
+            self.range.end(),
+            self.count(),
+            self.sum()
+        )?;
+        for (i, id) in self.invalid_ids.iter().enumerate() {
+            write!(f, "{}{id}", if i == 0 { ": " } else { ", " })?;
         }
-        let substring_length = id.len() / factor;
-        let pattern = &id[0..substring_length];
-
-        let mut pattern_matches = true;
-        for i in 1..factor {
-            let substring = &id[(substring_length * i)..(substring_length * i + substring_length)];
-            if substring != pattern {
-                pattern_matches = false;
+        Ok(())
+    }
+}
+
+pub fn report(ranges: &[RangeInclusive<u64>], rule: RepetitionRule) -> Vec<RangeReport> {
+    ranges
+        .iter()
+        .map(|range| RangeReport {
+            range: range.clone(),
+            invalid_ids: rule.invalid_ids(range),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::XorShift;
+
+    fn random_ranges(count: usize) -> Vec<RangeInclusive<u64>> {
+        let mut random = XorShift::new(0x9e3779b97f4a7c15);
+        (0..count)
+            .map(|_| {
+                // Up to 12 digits, spanning up to a few hundred thousand IDs
+                let start = random.next_u64() % 10u64.pow(1 + random.below(12) as u32);
+                start..=start + random.below(300_000)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sum_matches_listed_ids() {
+        let rules = [
+            RepetitionRule::exactly(2),
+            RepetitionRule::exactly(3),
+            RepetitionRule::at_least(2),
+            RepetitionRule::at_least(3),
+            RepetitionRule::at_most(3),
+            RepetitionRule::between(3, 4),
+            RepetitionRule::at_least(2).in_base(2),
+            RepetitionRule::exactly(2).in_base(16),
+        ];
+        for range in random_ranges(200) {
+            for rule in rules {
+                let listed: u128 = rule.invalid_ids(&range).iter().map(|&id| id as u128).sum();
+                assert_eq!(
+                    rule.invalid_id_sum(&range),
+                    listed,
+                    "{rule:?} over {range:?}"
+                );
             }
         }
-        if pattern_matches {
-            return true;
-        }
     }
-    false
+
+    #[test]
+    fn sum_over_whole_domain() {
+        // Far too many IDs to list, the sum comes from the block ranges alone
+        let rule = RepetitionRule::at_least(2);
+        let split = 10u64.pow(10);
+        assert_eq!(
+            rule.invalid_id_sum(&(0..=u64::MAX)),
+            rule.invalid_id_sum(&(0..=split - 1)) + rule.invalid_id_sum(&(split..=u64::MAX))
+        );
+        assert_eq!(
+            rule.invalid_id_sum(&(0..=99)),
+            (1..=9).map(|d| d * 11).sum()
+        );
+        assert_eq!(rule.invalid_id_sum(&RangeInclusive::new(5, 4)), 0);
+    }
+
+    #[test]
+    fn example_totals() {
+        let ranges = parse_ranges(
+            "11-22,95-115,998-1012,1188511880-1188511890,222220-222224,\
+             1698522-1698528,446443-446449,38593856-38593862,565653-565659,\
+             824824821-824824827,2121212118-2121212124",
+        );
+        let total = |rule: RepetitionRule| -> u128 {
+            ranges.iter().map(|range| rule.invalid_id_sum(range)).sum()
+        };
+        assert_eq!(total(RepetitionRule::exactly(2)), 1227775554);
+        assert_eq!(total(RepetitionRule::at_least(2)), 4174379265);
+    }
+
+    #[test]
+    fn range_reports() {
+        let ranges = parse_ranges("11-22,95-115,1698522-1698528");
+        let reports = report(&ranges, RepetitionRule::at_least(2));
+        assert_eq!(
+            reports,
+            [
+                RangeReport {
+                    range: 11..=22,
+                    invalid_ids: vec![11, 22]
+                },
+                RangeReport {
+                    range: 95..=115,
+                    invalid_ids: vec![99, 111]
+                },
+                RangeReport {
+                    range: 1698522..=1698528,
+                    invalid_ids: vec![]
+                },
+            ]
+        );
+        assert_eq!(reports[1].count(), 2);
+        assert_eq!(reports[1].sum(), 210);
+
+        let lines: Vec<String> = reports.iter().map(RangeReport::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "11-22: 2 invalid, sum 33: 11, 22",
+                "95-115: 2 invalid, sum 210: 99, 111",
+                "1698522-1698528: 0 invalid, sum 0",
+            ]
+        );
+        assert_eq!(
+            report(&ranges, RepetitionRule::exactly(2))[1].invalid_ids,
+            [99]
+        );
+    }
+
+    #[test]
+    fn range_report_sum_past_u64() {
+        let reports = report(
+            &[18446744061844674406..=u64::MAX],
+            RepetitionRule::exactly(2),
+        );
+        assert_eq!(
+            reports[0].invalid_ids,
+            [18446744061844674406, 18446744071844674407]
+        );
+        assert_eq!(reports[0].sum(), 36893488133689348813);
+    }
 }
@@ -1,66 +1,213 @@
-use crate::memoizer::{HashRef, Memoizer};
-type MyMemoizer<'a> = Memoizer<(HashRef<'a, [u8]>, u8), u64>;
+use std::fmt;
+
+use crate::memoized;
 
 pub struct Day3;
 
 impl crate::Day for Day3 {
     fn run(input: String) -> crate::DayResult {
-        let battery_banks = input;
-
         let mut total_joltage_part_1: u64 = 0;
         let mut total_joltage_part_2: u64 = 0;
 
-        for bank in battery_banks.lines() {
-            let batteries = bank
-                .chars()
+        for batteries in parse_banks(&input) {
+            total_joltage_part_1 += select_largest_digits(&batteries, 2).value().unwrap();
+            total_joltage_part_2 += select_largest_digits(&batteries, 12).value().unwrap();
+        }
+
+        crate::DayResult {
+            part_1: total_joltage_part_1,
+            part_2: total_joltage_part_2,
+        }
+    }
+}
+
+pub fn parse_banks(input: &str) -> Vec<Vec<u8>> {
+    input
+        .lines()
+        .map(|bank| {
+            bank.chars()
                 .map(|digit| {
                     digit
                         .to_digit(10)
                         .expect("couldn't convert input to number") as u8
                 })
-                .collect::<Vec<u8>>();
+                .collect()
+        })
+        .collect()
+}
 
-            let max_part_1 =
-                largest_ordered_digits(batteries.as_slice(), 2, &mut MyMemoizer::new());
-            total_joltage_part_1 += max_part_1;
+/// Sum over every bank of its largest num_digits-digit joltage
+pub fn total_joltage(banks: &[Vec<u8>], num_digits: usize) -> DecimalSum {
+    let mut total = DecimalSum::default();
+    for bank in banks {
+        total.add(&select_largest_digits(bank, num_digits).digits);
+    }
+    total
+}
+
+/// Total joltage for every num_digits from 1 up to the shortest bank's length
+pub fn joltage_table(banks: &[Vec<u8>]) -> Vec<(usize, DecimalSum)> {
+    let max_digits = banks.iter().map(Vec::len).min().unwrap_or(0);
+    (1..=max_digits)
+        .map(|num_digits| (num_digits, total_joltage(banks, num_digits)))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigitSelection {
+    /// Indices of the chosen digits, in increasing order
+    pub positions: Vec<usize>,
+    /// The chosen digits, most significant first
+    pub digits: Vec<u8>,
+}
+
+impl DigitSelection {
+    /// The selected number, or None if it doesn't fit in a u64
+    pub fn value(&self) -> Option<u64> {
+        self.digits.iter().try_fold(0u64, |acc, &digit| {
+            acc.checked_mul(10)?.checked_add(digit as u64)
+        })
+    }
+}
+
+impl fmt::Display for DigitSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_decimal(f, self.digits.iter().copied())
+    }
+}
 
-            let max_part_2 =
-                largest_ordered_digits(batteries.as_slice(), 12, &mut MyMemoizer::new());
-            total_joltage_part_2 += max_part_2;
+/// Arbitrary precision running total, for selections too long for a u64
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecimalSum {
+    digits: Vec<u8>, // Least significant first
+}
+
+impl DecimalSum {
+    /// Adds a number given as decimal digits, most significant first
+    pub fn add(&mut self, digits: &[u8]) {
+        if self.digits.len() < digits.len() {
+            self.digits.resize(digits.len(), 0);
         }
 
-        crate::DayResult {
-            part_1: total_joltage_part_1,
-            part_2: total_joltage_part_2,
+        let mut carry = 0;
+        let mut addend = digits.iter().rev();
+        for digit in self.digits.iter_mut() {
+            let sum = *digit + addend.next().copied().unwrap_or(0) + carry;
+            *digit = sum % 10;
+            carry = sum / 10;
+        }
+        if carry > 0 {
+            self.digits.push(carry);
         }
     }
 }
 
-fn largest_ordered_digits<'a>(
-    digits: &'a [u8],
-    num_digits: u8,
-    memo_table: &mut MyMemoizer<'a>,
-) -> u64 {
-    let memo_key = (HashRef { _ref: digits }, num_digits);
-
-    if let Some(cached) = memo_table.get(&memo_key) {
-        return *cached;
+impl fmt::Display for DecimalSum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_decimal(f, self.digits.iter().rev().copied())
     }
+}
+
+// Writes digits (most significant first) without leading zeros
+fn write_decimal(f: &mut fmt::Formatter<'_>, digits: impl Iterator<Item = u8>) -> fmt::Result {
+    let digits: String = digits
+        .skip_while(|&digit| digit == 0)
+        .map(|digit| char::from(b'0' + digit))
+        .collect();
+    f.pad(if digits.is_empty() { "0" } else { &digits })
+}
+
+/// Picks the num_digits digits (kept in order) that form the largest number.
+/// Monotonic stack: a digit is dropped whenever a bigger one follows it and
+/// there are still digits to spare, so this is O(n) for any num_digits
+pub fn select_largest_digits(digits: &[u8], num_digits: usize) -> DigitSelection {
+    assert!(
+        num_digits <= digits.len(),
+        "Can't pick {num_digits} digits from {}",
+        digits.len()
+    );
 
-    if num_digits == 1 {
-        return *digits.iter().max().unwrap() as u64;
+    let mut spare = digits.len() - num_digits;
+    let mut positions: Vec<usize> = Vec::with_capacity(digits.len());
+    for (index, &digit) in digits.iter().enumerate() {
+        while spare > 0 && positions.last().is_some_and(|&top| digits[top] < digit) {
+            positions.pop();
+            spare -= 1;
+        }
+        positions.push(index);
     }
+    positions.truncate(num_digits);
 
-    let mut max = 0;
-    for i in 0..(digits.len() - (num_digits as usize) + 1) {
-        let max_from_here = digits[i] as u64 * 10u64.pow(num_digits as u32 - 1)
-            + largest_ordered_digits(&digits[(i + 1)..], num_digits - 1, memo_table);
-        if max_from_here > max {
-            max = max_from_here;
+    let digits = positions.iter().map(|&index| digits[index]).collect();
+    DigitSelection { positions, digits }
+}
+
+// Reference implementation for select_largest_digits, O(n^2 * k).
+// Only valid while the result fits in a u64 (num_digits <= 19)
+memoized! {
+    // Every recursive call sees a suffix of the same bank, so its length identifies it
+    pub fn largest_ordered_digits(digits: &[u8], num_digits: u8) -> u64
+    where dense key: [usize; 2] = [digits.len(), num_digits as usize],
+        bounds = [digits.len() + 1, num_digits as usize + 1]
+    {
+        if num_digits == 1 {
+            return *digits.iter().max().unwrap() as u64;
         }
+
+        (0..(digits.len() - (num_digits as usize) + 1))
+            .map(|i| {
+                digits[i] as u64 * 10u64.pow(num_digits as u32 - 1)
+                    + largest_ordered_digits(&digits[(i + 1)..], num_digits - 1)
+            })
+            .max()
+            .unwrap()
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::XorShift;
 
-    memo_table.insert(memo_key, max);
+    fn random_banks(count: usize) -> Vec<Vec<u8>> {
+        let mut random = XorShift::new(0x2545f4914f6cdd1d);
+        (0..count)
+            .map(|_| {
+                let len = 1 + random.below(40) as usize;
+                // Small alphabets give lots of ties between equal digits
+                let alphabet = 1 + random.below(10);
+                (0..len)
+                    .map(|_| (9 - random.below(alphabet)) as u8)
+                    .collect()
+            })
+            .collect()
+    }
 
-    max
+    #[test]
+    fn greedy_matches_reference() {
+        for bank in random_banks(500) {
+            for num_digits in 1..=bank.len().min(19) {
+                let selection = select_largest_digits(&bank, num_digits);
+                assert_eq!(
+                    selection.value(),
+                    Some(largest_ordered_digits(&bank, num_digits as u8)),
+                    "bank {bank:?}, {num_digits} digits"
+                );
+
+                assert_eq!(selection.positions.len(), num_digits);
+                assert!(selection.positions.windows(2).all(|pair| pair[0] < pair[1]));
+                for (&position, &digit) in selection.positions.iter().zip(&selection.digits) {
+                    assert_eq!(bank[position], digit, "bank {bank:?}, {num_digits} digits");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn example_banks() {
+        let banks =
+            parse_banks("987654321111111\n811111111111119\n234234234234278\n818181911112111");
+        assert_eq!(total_joltage(&banks, 2).to_string(), "357");
+        assert_eq!(total_joltage(&banks, 12).to_string(), "3121910778619");
+    }
 }
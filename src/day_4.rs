@@ -1,8 +1,14 @@
+use std::fmt;
+
+use crate::fx_hash::{FxHashMap, FxHashSet};
+use crate::grid::{Grid, ORTHOGONAL, SURROUNDING};
+
 pub struct Day4;
 
 impl crate::Day for Day4 {
     fn run(input: String) -> crate::DayResult {
-        let mut grid = PaperRollGrid::new(input);
+        let mut grid = BitRollGrid::new(&input);
+
         crate::DayResult {
             part_1: grid.count_accessible_rolls(),
             part_2: grid.remove_rolls(),
@@ -10,22 +16,85 @@ impl crate::Day for Day4 {
     }
 }
 
-struct PaperRollGrid {
-    height: usize,
-    width: usize,
-    grid: Vec<Vec<bool>>, //Is there a roll of paper at this position or not.
+/// Which positions count as a roll's neighbours
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// All 8 surrounding positions
+    Moore,
+    /// The 4 orthogonal positions
+    VonNeumann,
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighbourhood {
+    pub fn offsets(&self) -> &[(isize, isize)] {
+        match self {
+            Neighbourhood::Moore => &SURROUNDING,
+            Neighbourhood::VonNeumann => &ORTHOGONAL,
+            Neighbourhood::Custom(offsets) => offsets,
+        }
+    }
+}
+
+/// What a neighbour off the edge of the grid counts as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeRule {
+    Empty,
+    Full,
+    /// The grid is a torus, so there is no edge. Every offset counts on its
+    /// own, so on a grid narrower or shorter than the neighbourhood reaches
+    /// the same roll can count more than once, the roll itself included:
+    /// the only roll on a 1x1 grid is its own Moore neighbour 8 times
+    Wrap,
+}
+
+/// A roll is accessible when fewer than `threshold` of its neighbours are rolls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRule {
+    pub threshold: u32,
+    pub neighbourhood: Neighbourhood,
+    pub edges: EdgeRule,
+}
+
+impl Default for AccessRule {
+    fn default() -> Self {
+        AccessRule {
+            threshold: 4,
+            neighbourhood: Neighbourhood::Moore,
+            edges: EdgeRule::Empty,
+        }
+    }
+}
+
+/// Accessibility and removal, shared by the dense, bit-packed and sparse grids
+pub trait RollGrid {
+    /// Rolls with fewer than the threshold of neighbouring rolls
+    fn count_accessible_rolls(&self) -> u64;
+
+    /// Removes accessible rolls in waves until none are left, returning how many
+    /// went in each wave. Each wave is every roll that was accessible once the
+    /// previous wave was gone
+    fn removal_waves(&mut self) -> Vec<u64>;
+
+    fn remove_rolls(&mut self) -> u64 {
+        self.removal_waves().iter().sum()
+    }
+}
+
+pub struct PaperRollGrid {
+    grid: Grid<bool>, //Is there a roll of paper at this position or not.
+    rule: AccessRule,
 }
 
 impl PaperRollGrid {
     pub fn new(input: String) -> Self {
-        let grid: Vec<Vec<bool>> = input.lines().map(Self::process_line).collect();
-        let height = grid.len();
-        let width = if height > 0 { grid[0].len() } else { 0 };
+        Self::with_rule(input, AccessRule::default())
+    }
 
+    pub fn with_rule(input: String, rule: AccessRule) -> Self {
         PaperRollGrid {
-            height,
-            width,
-            grid,
+            grid: Grid::from_rows(input.lines().map(Self::process_line).collect()),
+            rule,
         }
     }
 
@@ -33,63 +102,799 @@ impl PaperRollGrid {
         line.trim().chars().map(|c| c == '@').collect()
     }
 
-    pub fn remove_rolls(&mut self) -> u64 {
-        let mut removed_rolls = 0;
-        while self.count_accessible_rolls() > 0 {
-            for y in 0..self.height {
-                for x in 0..self.width {
-                    if self.grid[y][x] && self.count_adjacent_rolls(x, y) < 4 {
-                        self.grid[y][x] = false;
-                        removed_rolls += 1;
+    // Removes rolls as in removal_waves, recording the wave each one went in.
+    // Keeps a neighbour count per roll and only re-examines the neighbours of
+    // removed rolls, rather than rescanning the whole grid every wave.
+    pub fn removal_map(&mut self) -> RemovalMap {
+        let mut removal_times = self.grid.map(|&roll| {
+            if roll {
+                RemovalTime::Never
+            } else {
+                RemovalTime::Empty
+            }
+        });
+
+        let mut adjacent_counts = self.grid.map(|_| 0);
+        let mut wave = Vec::new();
+        for (x, y, &roll) in self.grid.iter() {
+            if roll {
+                adjacent_counts[(x, y)] = self.count_adjacent_rolls(x, y);
+                if adjacent_counts[(x, y)] < self.rule.threshold {
+                    wave.push((x, y));
+                }
+            }
+        }
+
+        let mut wave_number = 1;
+        while !wave.is_empty() {
+            for &(x, y) in &wave {
+                self.grid[(x, y)] = false;
+                removal_times[(x, y)] = RemovalTime::Wave(wave_number);
+            }
+
+            let mut next_wave = Vec::new();
+            for (x, y) in wave {
+                // The rolls that see (x, y) are the ones at the reversed offsets
+                for &(dx, dy) in self.rule.neighbourhood.offsets() {
+                    let Some(position) = self.resolve(x, y, -dx, -dy) else {
+                        continue;
+                    };
+                    if !self.grid[position] {
+                        continue;
+                    }
+                    // A roll joins a wave exactly once, when its count first drops below the threshold
+                    adjacent_counts[position] -= 1;
+                    if adjacent_counts[position] + 1 == self.rule.threshold {
+                        next_wave.push(position);
                     }
                 }
             }
+            wave = next_wave;
+            wave_number += 1;
+        }
+
+        RemovalMap {
+            grid: removal_times,
         }
-        removed_rolls
     }
 
-    pub fn count_accessible_rolls(&self) -> u64 {
-        let mut accessible_count = 0;
-        for y in 0..self.height {
-            for x in 0..self.width {
-                if self.grid[y][x] && self.count_adjacent_rolls(x, y) < 4 {
-                    accessible_count += 1;
+    fn count_adjacent_rolls(&self, x: usize, y: usize) -> u32 {
+        self.rule
+            .neighbourhood
+            .offsets()
+            .iter()
+            .filter(|&&(dx, dy)| match self.resolve(x, y, dx, dy) {
+                Some(position) => self.grid[position],
+                None => self.rule.edges == EdgeRule::Full,
+            })
+            .count() as u32
+    }
+
+    // The on-grid position (dx, dy) away from (x, y), after wrapping if the edges wrap
+    fn resolve(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+        match self.rule.edges {
+            EdgeRule::Wrap => Some((
+                (x as isize + dx).rem_euclid(self.grid.width() as isize) as usize,
+                (y as isize + dy).rem_euclid(self.grid.height() as isize) as usize,
+            )),
+            EdgeRule::Empty | EdgeRule::Full => self.grid.offset(x, y, dx, dy),
+        }
+    }
+}
+
+impl RollGrid for PaperRollGrid {
+    fn count_accessible_rolls(&self) -> u64 {
+        self.grid
+            .iter()
+            .filter(|&(x, y, &roll)| roll && self.count_adjacent_rolls(x, y) < self.rule.threshold)
+            .count() as u64
+    }
+
+    fn removal_waves(&mut self) -> Vec<u64> {
+        self.removal_map().wave_sizes()
+    }
+}
+
+/// When the roll at a position was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalTime {
+    /// There was never a roll here
+    Empty,
+    /// Removed in this wave, counting from 1
+    Wave(u32),
+    /// The roll is still there once nothing more can be removed
+    Never,
+}
+
+impl fmt::Display for RemovalTime {
+    // Single character per cell: waves 1-35 as base 36 digits, '+' past that
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = match *self {
+            RemovalTime::Empty => '.',
+            RemovalTime::Never => '@',
+            RemovalTime::Wave(wave) => char::from_digit(wave, 36).unwrap_or('+'),
+        };
+        write!(f, "{c}")
+    }
+}
+
+/// Grid of when each roll was removed, see PaperRollGrid::removal_map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovalMap {
+    grid: Grid<RemovalTime>,
+}
+
+impl RemovalMap {
+    pub fn get(&self, x: usize, y: usize) -> Option<RemovalTime> {
+        self.grid.get(x, y).copied()
+    }
+
+    pub fn last_wave(&self) -> u32 {
+        self.grid
+            .iter()
+            .filter_map(|(_, _, time)| match time {
+                RemovalTime::Wave(wave) => Some(*wave),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// How many rolls went in each wave
+    pub fn wave_sizes(&self) -> Vec<u64> {
+        let mut sizes = vec![0; self.last_wave() as usize];
+        for (_, _, time) in self.grid.iter() {
+            if let RemovalTime::Wave(wave) = time {
+                sizes[*wave as usize - 1] += 1;
+            }
+        }
+        sizes
+    }
+
+    /// Same layout as the input, see RemovalTime's Display for the characters
+    pub fn to_text(&self) -> String {
+        self.grid.to_string()
+    }
+
+    /// One line per row: the wave number, "never", or an empty field where there was no roll
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in self.grid.rows() {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|time| match time {
+                    RemovalTime::Empty => String::new(),
+                    RemovalTime::Wave(wave) => wave.to_string(),
+                    RemovalTime::Never => "never".to_string(),
+                })
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Binary PPM (P6) heatmap, one pixel per cell. Waves fade from blue (first)
+    /// to red (last), rolls that stay are white and empty space is black
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let last_wave = self.last_wave();
+        let mut ppm =
+            format!("P6\n{} {}\n255\n", self.grid.width(), self.grid.height()).into_bytes();
+
+        for (_, _, time) in self.grid.iter() {
+            let pixel = match *time {
+                RemovalTime::Empty => [0, 0, 0],
+                RemovalTime::Never => [255, 255, 255],
+                RemovalTime::Wave(wave) => {
+                    let lateness = if last_wave > 1 {
+                        (wave - 1) as f64 / (last_wave - 1) as f64
+                    } else {
+                        0.0
+                    };
+                    let red = (255.0 * lateness).round() as u8;
+                    [red, 0, 255 - red]
                 }
+            };
+            ppm.extend_from_slice(&pixel);
+        }
+        ppm
+    }
+}
+
+impl fmt::Display for RemovalMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.grid)
+    }
+}
+
+/// Bit-packed roll grid, each row stored as ceil(width / 64) u64 words.
+/// Neighbour counts for 64 cells at a time come from shifting the rows
+/// above, below and beside into place and summing them in a bit-sliced
+/// counter. Supports the default Moore neighbourhood with empty edges,
+/// with a configurable threshold; PaperRollGrid is the general version
+pub struct BitRollGrid {
+    words_per_row: usize,
+    height: usize,
+    threshold: u32,
+    rows: Vec<u64>, // Bit x % 64 of word x / 64 is the cell at column x, bits past the width are 0
+}
+
+impl BitRollGrid {
+    pub fn new(input: &str) -> Self {
+        Self::with_threshold(input, AccessRule::default().threshold)
+    }
+
+    pub fn with_threshold(input: &str, threshold: u32) -> Self {
+        let lines: Vec<&str> = input.lines().map(str::trim).collect();
+        let width = lines.first().map_or(0, |line| line.len());
+        let words_per_row = width.div_ceil(64);
+
+        let mut rows = vec![0; words_per_row * lines.len()];
+        for (y, line) in lines.iter().enumerate() {
+            assert_eq!(line.len(), width, "Row {y} has a different width");
+            for (word, chunk) in line.as_bytes().chunks(64).enumerate() {
+                rows[y * words_per_row + word] = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (bit, &c)| acc | ((c == b'@') as u64) << bit);
             }
         }
-        accessible_count
+
+        BitRollGrid {
+            words_per_row,
+            height: lines.len(),
+            threshold,
+            rows,
+        }
+    }
+
+    // Bit set for every roll in words[word] of row y with fewer than
+    // threshold neighbouring rolls
+    fn accessible_word(&self, y: usize, word: usize) -> u64 {
+        let index = y * self.words_per_row + word;
+        let [above_left, above, above_right] = if y > 0 {
+            self.shifted(index - self.words_per_row, word)
+        } else {
+            [0; 3]
+        };
+        let [left, current, right] = self.shifted(index, word);
+        let [below_left, below, below_right] = if y + 1 < self.height {
+            self.shifted(index + self.words_per_row, word)
+        } else {
+            [0; 3]
+        };
+
+        // Carry-save adder tree summing the 8 neighbours into a 4-bit
+        // counter per cell, counter[i] holding bit i of every cell's count
+        let (ones_a, twos_a) = Self::full_add(above_left, above, above_right);
+        let (ones_b, twos_b) = Self::full_add(below_left, below, below_right);
+        let (ones_c, twos_c) = (left ^ right, left & right);
+        let (ones, twos_d) = Self::full_add(ones_a, ones_b, ones_c);
+        let (twos_abc, fours_a) = Self::full_add(twos_a, twos_b, twos_c);
+        let (twos, fours_b) = (twos_abc ^ twos_d, twos_abc & twos_d);
+        let counter = [ones, twos, fours_a ^ fours_b, fours_a & fours_b];
+
+        current & Self::less_than(&counter, self.threshold)
     }
 
-    fn count_adjacent_rolls(&self, x: usize, y: usize) -> u8 {
+    // The word at `index` (word `word` of its row) shifted so bit x is the
+    // cell at x - 1, unshifted, and shifted so bit x is the cell at x + 1
+    fn shifted(&self, index: usize, word: usize) -> [u64; 3] {
+        let centre = self.rows[index];
+        let from_left = if word > 0 {
+            self.rows[index - 1] >> 63
+        } else {
+            0
+        };
+        let from_right = if word + 1 < self.words_per_row {
+            self.rows[index + 1] << 63
+        } else {
+            0
+        };
         [
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
+            (centre << 1) | from_left,
+            centre,
+            (centre >> 1) | from_right,
         ]
-        .map(|(dx, dy)| {
-            if self.check_direction(x, y, dx, dy) {
-                1
+    }
+
+    // Per-bit sum of three bits, as (sum, carry)
+    fn full_add(a: u64, b: u64, c: u64) -> (u64, u64) {
+        let partial = a ^ b;
+        (partial ^ c, (a & b) | (partial & c))
+    }
+
+    // Bit set for every cell whose count is below the threshold,
+    // comparing from the most significant bit down
+    fn less_than(counter: &[u64; 4], threshold: u32) -> u64 {
+        if threshold >= 16 {
+            return u64::MAX;
+        }
+
+        let mut less = 0;
+        let mut equal = u64::MAX;
+        for (i, bit) in counter.iter().enumerate().rev() {
+            if threshold & (1 << i) != 0 {
+                less |= equal & !bit;
+                equal &= bit;
             } else {
-                0
+                equal &= !bit;
+            }
+        }
+        less
+    }
+}
+
+impl RollGrid for BitRollGrid {
+    fn count_accessible_rolls(&self) -> u64 {
+        (0..self.height)
+            .flat_map(|y| (0..self.words_per_row).map(move |word| (y, word)))
+            .map(|(y, word)| self.accessible_word(y, word).count_ones() as u64)
+            .sum()
+    }
+
+    // Only words next to a word that lost rolls in the previous wave can
+    // have newly accessible rolls, so those are the only ones recomputed.
+    // A word's neighbours to the side only count if the rolls removed
+    // were on that edge of it. Each row keeps a bit per word saying
+    // whether it's dirty, words with no rolls left are never marked, and
+    // the buffers are reused from wave to wave
+    fn removal_waves(&mut self) -> Vec<u64> {
+        if self.rows.is_empty() {
+            return Vec::new();
+        }
+        let masks_per_row = self.words_per_row.div_ceil(64);
+        let mut dirty = vec![0u64; masks_per_row * self.height];
+        let mut next_dirty = dirty.clone();
+        for (y, row_masks) in dirty.chunks_exact_mut(masks_per_row).enumerate() {
+            for word in 0..self.words_per_row {
+                if self.rows[y * self.words_per_row + word] != 0 {
+                    row_masks[word / 64] |= 1 << (word % 64);
+                }
             }
+        }
+        let mut updates: Vec<(usize, usize, u64)> = Vec::new();
+
+        let mut waves = Vec::new();
+        loop {
+            updates.clear();
+            for (y, row_masks) in dirty.chunks_exact(masks_per_row).enumerate() {
+                for (chunk, &mask) in row_masks.iter().enumerate() {
+                    let mut mask = mask;
+                    while mask != 0 {
+                        let word = chunk * 64 + mask.trailing_zeros() as usize;
+                        mask &= mask - 1;
+                        let accessible = self.accessible_word(y, word);
+                        if accessible != 0 {
+                            updates.push((y, word, accessible));
+                        }
+                    }
+                }
+            }
+            if updates.is_empty() {
+                return waves;
+            }
+
+            let mut removed = 0;
+            for &(y, word, accessible) in &updates {
+                removed += accessible.count_ones() as u64;
+                self.rows[y * self.words_per_row + word] &= !accessible;
+
+                let first_word = if accessible & 1 != 0 {
+                    word.saturating_sub(1)
+                } else {
+                    word
+                };
+                let last_word = if accessible >> 63 != 0 {
+                    usize::min(word + 1, self.words_per_row - 1)
+                } else {
+                    word
+                };
+                for y in y.saturating_sub(1)..=usize::min(y + 1, self.height - 1) {
+                    let row = &self.rows[y * self.words_per_row..(y + 1) * self.words_per_row];
+                    let row_masks = &mut next_dirty[y * masks_per_row..(y + 1) * masks_per_row];
+                    // Words with no rolls left can't have any become accessible
+                    for word in (first_word..=last_word).filter(|&word| row[word] != 0) {
+                        row_masks[word / 64] |= 1 << (word % 64);
+                    }
+                }
+            }
+            std::mem::swap(&mut dirty, &mut next_dirty);
+            next_dirty.fill(0);
+            waves.push(removed);
+        }
+    }
+}
+
+/// A rule asked a sparse grid to wrap or fill edges it doesn't have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedEdgeRule(pub EdgeRule);
+
+impl fmt::Display for UnsupportedEdgeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A sparse grid has no edges, so it can't use EdgeRule::{:?}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedEdgeRule {}
+
+/// Rolls stored as a set of coordinates in unbounded space, for layouts
+/// too big or sparse to allocate densely, or given as coordinate lists.
+/// Everything off the set is empty, so the rule's edge handling doesn't
+/// apply and must be EdgeRule::Empty. Neighbours past i64::MIN or
+/// i64::MAX are off the set too
+pub struct SparseRollGrid {
+    rolls: FxHashSet<(i64, i64)>,
+    rule: AccessRule,
+}
+
+impl SparseRollGrid {
+    pub fn new(rolls: impl IntoIterator<Item = (i64, i64)>) -> Self {
+        SparseRollGrid {
+            rolls: rolls.into_iter().collect(),
+            rule: AccessRule::default(),
+        }
+    }
+
+    pub fn with_rule(
+        rolls: impl IntoIterator<Item = (i64, i64)>,
+        rule: AccessRule,
+    ) -> Result<Self, UnsupportedEdgeRule> {
+        if rule.edges != EdgeRule::Empty {
+            return Err(UnsupportedEdgeRule(rule.edges));
+        }
+        Ok(SparseRollGrid {
+            rolls: rolls.into_iter().collect(),
+            rule,
         })
-        .iter()
-        .sum()
     }
 
-    fn check_direction(&self, x: usize, y: usize, dx: isize, dy: isize) -> bool {
-        let new_x = x as isize + dx;
-        let new_y = y as isize + dy;
+    /// The same '@' layout PaperRollGrid reads, lines may be ragged
+    pub fn from_layout(input: &str) -> Self {
+        Self::new(input.lines().enumerate().flat_map(|(y, line)| {
+            line.trim()
+                .chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '@')
+                .map(move |(x, _)| (x as i64, y as i64))
+        }))
+    }
+
+    /// One "x,y" roll position per line
+    pub fn from_coordinates(input: &str) -> Self {
+        Self::new(input.lines().map(|line| {
+            let (x, y) = line
+                .trim()
+                .split_once(',')
+                .expect("Roll position should be x,y");
+            (
+                x.trim().parse().expect("Failed to parse x"),
+                y.trim().parse().expect("Failed to parse y"),
+            )
+        }))
+    }
+
+    pub fn insert(&mut self, x: i64, y: i64) {
+        self.rolls.insert((x, y));
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        self.rolls.contains(&(x, y))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rolls.len()
+    }
 
-        if new_x < 0 || new_x >= self.width as isize || new_y < 0 || new_y >= self.height as isize {
-            return false;
+    pub fn is_empty(&self) -> bool {
+        self.rolls.is_empty()
+    }
+
+    fn count_adjacent_rolls(&self, (x, y): (i64, i64)) -> u32 {
+        self.rule
+            .neighbourhood
+            .offsets()
+            .iter()
+            .filter_map(|&(dx, dy)| Some((x.checked_add(dx as i64)?, y.checked_add(dy as i64)?)))
+            .filter(|&(x, y)| self.contains(x, y))
+            .count() as u32
+    }
+}
+
+impl RollGrid for SparseRollGrid {
+    fn count_accessible_rolls(&self) -> u64 {
+        self.rolls
+            .iter()
+            .filter(|&&roll| self.count_adjacent_rolls(roll) < self.rule.threshold)
+            .count() as u64
+    }
+
+    // Same worklist as PaperRollGrid::removal_map, with the counts in a map
+    fn removal_waves(&mut self) -> Vec<u64> {
+        let mut adjacent_counts = FxHashMap::default();
+        let mut wave = Vec::new();
+        for &roll in &self.rolls {
+            let count = self.count_adjacent_rolls(roll);
+            adjacent_counts.insert(roll, count);
+            if count < self.rule.threshold {
+                wave.push(roll);
+            }
         }
 
-        self.grid[new_y as usize][new_x as usize]
+        let mut waves = Vec::new();
+        while !wave.is_empty() {
+            waves.push(wave.len() as u64);
+            for roll in &wave {
+                self.rolls.remove(roll);
+            }
+
+            let mut next_wave = Vec::new();
+            for (x, y) in wave {
+                // The rolls that see (x, y) are the ones at the reversed offsets
+                for &(dx, dy) in self.rule.neighbourhood.offsets() {
+                    let (Some(x), Some(y)) = (x.checked_sub(dx as i64), y.checked_sub(dy as i64))
+                    else {
+                        continue; // Off the set, past the end of an i64
+                    };
+                    let position = (x, y);
+                    if !self.rolls.contains(&position) {
+                        continue;
+                    }
+                    let count = adjacent_counts.get_mut(&position).unwrap();
+                    *count -= 1;
+                    if *count + 1 == self.rule.threshold {
+                        next_wave.push(position);
+                    }
+                }
+            }
+            wave = next_wave;
+        }
+        waves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::XorShift;
+
+    const EXAMPLE: &str = "..@@.@@@@.\n@@@.@.@.@@\n@@@@@.@.@@\n@.@@@@..@.\n@@.@@@@.@@\n\
+                           .@@@@@@@.@\n.@.@.@.@@@\n@.@@@.@@@@\n.@@@@@@@@.\n@.@.@@@.@.";
+
+    // Density varies from grid to grid so some are nearly empty and some nearly full
+    fn random_layout(random: &mut XorShift, width: usize, height: usize) -> String {
+        let density = 1 + random.below(9);
+        (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| if random.below(10) < density { '@' } else { '.' })
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn example() {
+        let mut grid = BitRollGrid::new(EXAMPLE);
+        assert_eq!(grid.count_accessible_rolls(), 13);
+        assert_eq!(grid.remove_rolls(), 43);
+    }
+
+    #[test]
+    fn bit_grid_matches_bool_grid() {
+        let mut random = XorShift::new(0x853c49e6748fea9b);
+        let widths = [1, 2, 7, 63, 64, 65, 100, 127, 128, 129, 190];
+        for &width in &widths {
+            for _ in 0..8 {
+                let height = 1 + random.below(40) as usize;
+                let layout = random_layout(&mut random, width, height);
+                for threshold in 0..=9 {
+                    let rule = AccessRule {
+                        threshold,
+                        ..AccessRule::default()
+                    };
+                    let mut bits = BitRollGrid::with_threshold(&layout, threshold);
+                    let mut reference = PaperRollGrid::with_rule(layout.clone(), rule);
+
+                    let context = format!("{width}x{height}, threshold {threshold}:\n{layout}");
+                    assert_eq!(
+                        bits.count_accessible_rolls(),
+                        reference.count_accessible_rolls(),
+                        "{context}"
+                    );
+                    assert_eq!(bits.removal_waves(), reference.removal_waves(), "{context}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_grid_matches_bool_grid() {
+        let mut random = XorShift::new(0x5851f42d4c957f2d);
+        for _ in 0..100 {
+            let (width, height) = (1 + random.below(30) as usize, 1 + random.below(30) as usize);
+            let layout = random_layout(&mut random, width, height);
+            let threshold = random.below(10) as u32;
+            let neighbourhood = if random.below(2) == 0 {
+                Neighbourhood::Moore
+            } else {
+                Neighbourhood::VonNeumann
+            };
+            let rule = AccessRule {
+                threshold,
+                neighbourhood,
+                edges: EdgeRule::Empty,
+            };
+
+            let rolls = SparseRollGrid::from_layout(&layout).rolls;
+            let mut sparse = SparseRollGrid::with_rule(rolls, rule.clone()).unwrap();
+            let mut reference = PaperRollGrid::with_rule(layout.clone(), rule);
+            assert_eq!(
+                sparse.count_accessible_rolls(),
+                reference.count_accessible_rolls(),
+                "{layout}"
+            );
+            assert_eq!(
+                sparse.removal_waves(),
+                reference.removal_waves(),
+                "{layout}"
+            );
+        }
+    }
+
+    #[test]
+    fn sparse_grid_at_the_ends_of_i64() {
+        // A full 3x3 block in each corner of the i64 plane, where every
+        // neighbour offset past the edge would overflow
+        let mut rolls = Vec::new();
+        for (x0, y0) in [
+            (i64::MIN, i64::MIN),
+            (i64::MIN, i64::MAX - 2),
+            (i64::MAX - 2, i64::MIN),
+            (i64::MAX - 2, i64::MAX - 2),
+        ] {
+            for dx in 0..3 {
+                for dy in 0..3 {
+                    rolls.push((x0 + dx, y0 + dy));
+                }
+            }
+        }
+
+        let mut sparse = SparseRollGrid::new(rolls);
+        let mut reference = PaperRollGrid::new("@@@\n@@@\n@@@\n".to_string());
+        let reference_waves: Vec<u64> = reference
+            .removal_waves()
+            .iter()
+            .map(|wave| wave * 4)
+            .collect();
+        assert_eq!(sparse.count_accessible_rolls(), 16);
+        assert_eq!(sparse.removal_waves(), reference_waves);
+        assert!(sparse.is_empty());
+    }
+
+    #[test]
+    fn sparse_grid_rejects_edge_rules() {
+        for edges in [EdgeRule::Full, EdgeRule::Wrap] {
+            let rule = AccessRule {
+                edges,
+                ..AccessRule::default()
+            };
+            assert_eq!(
+                SparseRollGrid::with_rule([(0, 0)], rule).err(),
+                Some(UnsupportedEdgeRule(edges))
+            );
+        }
+        assert!(SparseRollGrid::with_rule([(0, 0)], AccessRule::default()).is_ok());
+    }
+
+    fn rule(threshold: u32, neighbourhood: Neighbourhood, edges: EdgeRule) -> AccessRule {
+        AccessRule {
+            threshold,
+            neighbourhood,
+            edges,
+        }
+    }
+
+    #[test]
+    fn edge_rules() {
+        let layout = "@..\n...\n..@";
+        let count =
+            |rule| PaperRollGrid::with_rule(layout.to_string(), rule).count_accessible_rolls();
+
+        assert_eq!(count(rule(4, Neighbourhood::Moore, EdgeRule::Empty)), 2);
+        // Each corner roll has 5 neighbours off the grid
+        assert_eq!(count(rule(5, Neighbourhood::Moore, EdgeRule::Full)), 0);
+        assert_eq!(count(rule(6, Neighbourhood::Moore, EdgeRule::Full)), 2);
+        // The corners are diagonal neighbours across the wrap
+        assert_eq!(count(rule(1, Neighbourhood::Moore, EdgeRule::Wrap)), 0);
+        assert_eq!(count(rule(2, Neighbourhood::Moore, EdgeRule::Wrap)), 2);
+        assert_eq!(count(rule(1, Neighbourhood::VonNeumann, EdgeRule::Wrap)), 2);
+
+        let mut grid = PaperRollGrid::with_rule(
+            "@@@\n@@@\n@@@".to_string(),
+            rule(4, Neighbourhood::Moore, EdgeRule::Full),
+        );
+        assert_eq!(grid.removal_waves(), Vec::<u64>::new());
+        let mut grid = PaperRollGrid::with_rule(
+            "@@@\n@@@\n@@@".to_string(),
+            rule(8, Neighbourhood::Moore, EdgeRule::Wrap),
+        );
+        assert_eq!(grid.removal_waves(), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn von_neumann_neighbourhood() {
+        let mut grid = PaperRollGrid::with_rule(
+            "@@@\n@@@\n@@@".to_string(),
+            rule(3, Neighbourhood::VonNeumann, EdgeRule::Empty),
+        );
+        assert_eq!(grid.count_accessible_rolls(), 4);
+        assert_eq!(grid.removal_map().to_text(), "121\n232\n121");
+    }
+
+    #[test]
+    fn custom_neighbourhood() {
+        // Only the roll to the right counts, so rolls go from the right end of each run
+        let right = || Neighbourhood::Custom(vec![(1, 0)]);
+        let mut grid =
+            PaperRollGrid::with_rule("@@@@\n.@.@".to_string(), rule(1, right(), EdgeRule::Empty));
+        assert_eq!(grid.count_accessible_rolls(), 3);
+        assert_eq!(grid.removal_map().to_text(), "4321\n.1.1");
+
+        let mut grid =
+            PaperRollGrid::with_rule("@@@@".to_string(), rule(1, right(), EdgeRule::Wrap));
+        assert_eq!(grid.removal_waves(), Vec::<u64>::new());
+        let mut grid =
+            PaperRollGrid::with_rule("@@@.".to_string(), rule(1, right(), EdgeRule::Wrap));
+        assert_eq!(grid.removal_map().to_text(), "321.");
+    }
+
+    #[test]
+    fn wrap_counts_repeated_neighbours() {
+        let moore = |threshold, layout: &str| {
+            PaperRollGrid::with_rule(
+                layout.to_string(),
+                rule(threshold, Neighbourhood::Moore, EdgeRule::Wrap),
+            )
+        };
+
+        // The roll is all 8 of its own neighbours
+        assert_eq!(moore(8, "@").count_accessible_rolls(), 0);
+        assert_eq!(moore(9, "@").count_accessible_rolls(), 1);
+
+        // On one row, the rows above and below are that row again, so each
+        // roll counts itself twice and each of the others three times
+        assert_eq!(moore(8, "@@@").count_accessible_rolls(), 0);
+        assert_eq!(moore(9, "@@@").removal_waves(), [3]);
+        assert_eq!(moore(5, "@@.").removal_waves(), Vec::<u64>::new());
+        assert_eq!(moore(6, "@@.").removal_waves(), [2]);
+        assert_eq!(moore(5, "@\n@\n.").removal_waves(), Vec::<u64>::new());
+        assert_eq!(moore(6, "@\n@\n.").removal_waves(), [2]);
+    }
+
+    #[test]
+    fn removal_map_exports() {
+        // The bottom right roll goes first, then the one beside it, and the
+        // 2x2 block is left with 3 neighbours each
+        let mut grid = PaperRollGrid::with_rule(
+            "@@.\n@@.\n.@@".to_string(),
+            rule(3, Neighbourhood::Moore, EdgeRule::Empty),
+        );
+        let map = grid.removal_map();
+        assert_eq!(map.wave_sizes(), [1, 1]);
+        assert_eq!(map.to_text(), "@@.\n@@.\n.21");
+        assert_eq!(map.to_csv(), "never,never,\nnever,never,\n,2,1\n");
+
+        let header = b"P6\n3 3\n255\n";
+        let ppm = map.to_ppm();
+        assert_eq!(&ppm[..header.len()], header);
+        let (white, black, first, last) = ([255; 3], [0; 3], [0, 0, 255], [255, 0, 0]);
+        let pixels = [white, white, black, white, white, black, black, last, first];
+        assert_eq!(&ppm[header.len()..], pixels.as_flattened());
     }
 }
@@ -1,41 +1,346 @@
-use rangetools::Rangetools;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::interval_set::IntervalSet;
 
 pub struct Day5;
 
 impl crate::Day for Day5 {
     fn run(input: String) -> crate::DayResult {
-        let (ranges, ids) = input
-            .split_once("\n\n")
-            .expect("Input not formatted correctly");
-
-        let ranges: Vec<_> = ranges
-            .lines()
-            .map(|line| {
-                let (start, end) = line
-                    .trim()
-                    .split_once("-")
-                    .expect("Input not formatted correctly");
-                let start: u64 = start.parse().expect("Failed to parse range start");
-                let end: u64 = end.parse().expect("Failed to parse range end");
-                start..=end
-            })
-            .collect();
-
-        let full_range = ranges
-            .iter()
-            .skip(2)
-            .fold(ranges[0].clone().union(ranges[1].clone()), |acc, range| {
-                acc.union(range.clone())
-            });
+        let (ranges, ids) = parse_input(&input);
 
-        let ids: Vec<u64> = ids
-            .lines()
-            .map(|line| line.trim().parse().expect("Failed to parse ID"))
-            .collect();
+        let fresh_ids: IntervalSet<i128> = ranges.iter().cloned().collect();
 
         crate::DayResult {
-            part_1: ids.iter().filter(|id| full_range.contains(**id)).count() as u64,
-            part_2: full_range.into_iter().count() as u64,
+            part_1: ids.iter().filter(|id| fresh_ids.contains(**id)).count() as u64,
+            part_2: fresh_id_count(&fresh_ids),
+        }
+    }
+}
+
+/// Number of fresh IDs, clamped to u64::MAX. An open-ended range runs to
+/// the end of the i128 IDs, far more than a u64 holds, so any input using
+/// one comes out as u64::MAX, meaning "at least that many"
+pub fn fresh_id_count(fresh_ids: &IntervalSet<i128>) -> u64 {
+    fresh_ids
+        .covered_len()
+        .map_or(u64::MAX, |len| u64::try_from(len).unwrap_or(u64::MAX))
+}
+
+pub fn parse_input(input: &str) -> (Vec<RangeInclusive<i128>>, Vec<i128>) {
+    let (ranges, ids) = input
+        .split_once("\n\n")
+        .expect("Input not formatted correctly");
+
+    let ranges = ranges
+        .lines()
+        .map(|line| {
+            parse_range(line).unwrap_or_else(|err| panic!("Failed to parse range {line:?}: {err}"))
+        })
+        .collect();
+
+    let ids = ids
+        .lines()
+        .map(|line| line.trim().parse().expect("Failed to parse ID"))
+        .collect();
+
+    (ranges, ids)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeParseError {
+    MissingSeparator,
+    InvalidBound(String),
+}
+
+impl fmt::Display for RangeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeParseError::MissingSeparator => {
+                write!(f, "expected one of a-b, a..b, a..=b, a.. or ..b")
+            }
+            RangeParseError::InvalidBound(bound) => write!(f, "invalid bound {bound:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RangeParseError {}
+
+/// Parses any of
+///   a-b     inclusive, the original format (either bound may be negative)
+///   a..=b   inclusive
+///   a..b    half-open
+///   a..     open-ended, up to i128::MAX
+///   ..b     open-ended, down from i128::MIN (..=b works too)
+/// into an inclusive range. Half-open ranges with b <= a come out empty.
+/// Bounds are i128 so negative IDs fit alongside the whole u64 range
+pub fn parse_range(s: &str) -> Result<RangeInclusive<i128>, RangeParseError> {
+    let s = s.trim();
+    let parse_bound = |bound: &str| {
+        bound
+            .trim()
+            .parse::<i128>()
+            .map_err(|_| RangeParseError::InvalidBound(bound.to_string()))
+    };
+    let parse_optional = |bound: &str, default: i128| {
+        if bound.trim().is_empty() {
+            Ok(default)
+        } else {
+            parse_bound(bound)
+        }
+    };
+
+    if let Some((start, end)) = s.split_once("..=") {
+        return Ok(parse_optional(start, i128::MIN)?..=parse_bound(end)?);
+    }
+
+    if let Some((start, end)) = s.split_once("..") {
+        let start = parse_optional(start, i128::MIN)?;
+        if end.trim().is_empty() {
+            return Ok(start..=i128::MAX);
+        }
+        return Ok(match parse_bound(end)?.checked_sub(1) {
+            Some(end) => start..=end,
+            None => RangeInclusive::new(0, -1), // Nothing is below i128::MIN
+        });
+    }
+
+    // The separator is the first '-' that isn't the start's sign
+    let separator = s
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| c == '-')
+        .map(|(index, _)| index)
+        .ok_or(RangeParseError::MissingSeparator)?;
+    Ok(parse_bound(&s[..separator])?..=parse_bound(&s[separator + 1..])?)
+}
+
+/// Which of the original ranges an ID fell in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdClassification {
+    pub id: i128,
+    /// Indices into the range list, ascending. Empty if the ID is in a gap
+    pub covering_ranges: Vec<usize>,
+}
+
+impl IdClassification {
+    pub fn is_fresh(&self) -> bool {
+        !self.covering_ranges.is_empty()
+    }
+}
+
+// Sweeps the IDs in ascending order alongside the ranges sorted by start,
+// keeping a heap of the ranges that have started, ordered by end so finished
+// ones drop off the top. Results are in the same order as `ids`.
+pub fn classify_ids(ranges: &[RangeInclusive<i128>], ids: &[i128]) -> Vec<IdClassification> {
+    let mut range_order: Vec<usize> = (0..ranges.len()).collect();
+    range_order.sort_unstable_by_key(|&index| *ranges[index].start());
+    let mut id_order: Vec<usize> = (0..ids.len()).collect();
+    id_order.sort_unstable_by_key(|&index| ids[index]);
+
+    let mut classifications = vec![None; ids.len()];
+    let mut active = BinaryHeap::new();
+    let mut next_range = range_order.iter().peekable();
+    for id_index in id_order {
+        let id = ids[id_index];
+
+        while let Some(&index) = next_range.next_if(|&&index| *ranges[index].start() <= id) {
+            active.push(Reverse((*ranges[index].end(), index)));
+        }
+        while active.peek().is_some_and(|Reverse((end, _))| *end < id) {
+            active.pop();
         }
+
+        let mut covering_ranges: Vec<usize> =
+            active.iter().map(|Reverse((_, index))| *index).collect();
+        covering_ranges.sort_unstable();
+        classifications[id_index] = Some(IdClassification {
+            id,
+            covering_ranges,
+        });
+    }
+
+    classifications.into_iter().map(Option::unwrap).collect()
+}
+
+/// How the ranges in a list relate to each other, by index into the list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverlapReport {
+    /// Every pair of ranges sharing at least one ID, lower index first
+    pub overlaps: Vec<(usize, usize)>,
+    /// (inner, outer) for every range lying entirely inside another.
+    /// Of two identical ranges, the later one counts as inside the earlier
+    pub contained: Vec<(usize, usize)>,
+    /// IDs between the lowest start and the highest end that no range covers
+    pub gaps: Vec<RangeInclusive<i128>>,
+}
+
+// Sweeps the ranges by start (longest first on ties, so a range is always
+// seen before any range it contains), checking each against the ranges
+// still open at its start.
+pub fn overlap_report(ranges: &[RangeInclusive<i128>]) -> OverlapReport {
+    // Empty ranges can't overlap anything
+    let mut order: Vec<usize> = (0..ranges.len())
+        .filter(|&index| !ranges[index].is_empty())
+        .collect();
+    order.sort_by_key(|&index| (*ranges[index].start(), Reverse(*ranges[index].end()), index));
+
+    let mut report = OverlapReport::default();
+    let mut open: Vec<usize> = Vec::new();
+    for index in order {
+        let (start, end) = (*ranges[index].start(), *ranges[index].end());
+        open.retain(|&other| *ranges[other].end() >= start);
+
+        for &other in &open {
+            report
+                .overlaps
+                .push((usize::min(index, other), usize::max(index, other)));
+            if *ranges[other].end() >= end {
+                report.contained.push((index, other));
+            }
+        }
+        open.push(index);
+    }
+    report.overlaps.sort_unstable();
+    report.contained.sort_unstable();
+
+    let covered: IntervalSet<i128> = ranges.iter().cloned().collect();
+    if let (Some(first), Some(last)) = (covered.ranges().next(), covered.ranges().last()) {
+        let span = IntervalSet::from_ranges([*first.start()..=*last.end()]);
+        report.gaps = span.difference(&covered).ranges().collect();
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_past_i64() {
+        let max = u64::MAX as i128;
+        assert_eq!(
+            parse_range("18446744073709551614-18446744073709551615"),
+            Ok(max - 1..=max)
+        );
+        assert_eq!(parse_range("-5..=18446744073709551615"), Ok(-5..=max));
+
+        let (ranges, ids) = parse_input("0-18446744073709551615\n\n18446744073709551615\n");
+        let fresh_ids: IntervalSet<i128> = ranges.into_iter().collect();
+        assert!(fresh_ids.contains(ids[0]));
+        assert_eq!(fresh_ids.covered_len(), Some(1 << 64));
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        assert_eq!(parse_range("5.."), Ok(5..=i128::MAX));
+        assert_eq!(parse_range("..5"), Ok(i128::MIN..=4));
+        assert!(
+            parse_range("..-170141183460469231731687303715884105728")
+                .unwrap()
+                .is_empty()
+        );
+
+        // Every i128 is more than a u128 can count
+        let everything: IntervalSet<i128> =
+            [parse_range("..0").unwrap(), parse_range("0..").unwrap()]
+                .into_iter()
+                .collect();
+        assert_eq!(everything.covered_len(), None);
+    }
+
+    #[test]
+    fn open_ended_counts_clamp() {
+        use crate::Day;
+
+        let result = Day5::run("5..\n\n7\n4\n".to_string());
+        assert_eq!((result.part_1, result.part_2), (1, u64::MAX));
+        let result = Day5::run("..0\n3-4\n\n-7\n".to_string());
+        assert_eq!((result.part_1, result.part_2), (1, u64::MAX));
+
+        // Bounded ranges are counted exactly, right up to the clamp
+        let bounded = |input: &str| -> IntervalSet<i128> {
+            input
+                .lines()
+                .map(|line| parse_range(line).unwrap())
+                .collect()
+        };
+        assert_eq!(fresh_id_count(&bounded("-5-4\n10..=12")), 13);
+        assert_eq!(fresh_id_count(&bounded("1-18446744073709551615")), u64::MAX);
+        assert_eq!(fresh_id_count(&bounded("0-18446744073709551615")), u64::MAX);
+    }
+
+    fn ids(classifications: &[IdClassification]) -> Vec<(i128, Vec<usize>)> {
+        classifications
+            .iter()
+            .map(|c| (c.id, c.covering_ranges.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn classify_overlapping_ranges() {
+        let ranges = [1..=5, 3..=8, 2..=4, 10..=12, 3..=8];
+        // Range 2 has to be evicted by 5 and range 0 by 8
+        let classifications = classify_ids(&ranges, &[9, 3, 0, 12, 5, 8, 13]);
+        assert_eq!(
+            ids(&classifications),
+            [
+                (9, vec![]),
+                (3, vec![0, 1, 2, 4]),
+                (0, vec![]),
+                (12, vec![3]),
+                (5, vec![0, 1, 4]),
+                (8, vec![1, 4]),
+                (13, vec![]),
+            ]
+        );
+        assert_eq!(
+            classifications
+                .iter()
+                .map(|c| c.is_fresh())
+                .collect::<Vec<_>>(),
+            [false, true, false, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn classify_with_empty_ranges() {
+        let ranges = [RangeInclusive::new(4, 3), 1..=2];
+        assert_eq!(
+            ids(&classify_ids(&ranges, &[3, 4, 2])),
+            [(3, vec![]), (4, vec![]), (2, vec![1])]
+        );
+        assert!(classify_ids(&[], &[1]).iter().all(|c| !c.is_fresh()));
+    }
+
+    #[test]
+    fn overlaps() {
+        let report = overlap_report(&[1..=5, 3..=8, 2..=4, 10..=12, 3..=8]);
+        assert_eq!(
+            report,
+            OverlapReport {
+                overlaps: vec![(0, 1), (0, 2), (0, 4), (1, 2), (1, 4), (2, 4)],
+                // The later of the two identical ranges is the inner one
+                contained: vec![(2, 0), (4, 1)],
+                gaps: vec![9..=9],
+            }
+        );
+    }
+
+    #[test]
+    fn overlaps_with_empty_ranges_and_gaps() {
+        let report = overlap_report(&[20..=30, RangeInclusive::new(5, 4), 1..=3, 7..=7, 25..=25]);
+        assert_eq!(
+            report,
+            OverlapReport {
+                overlaps: vec![(0, 4)],
+                contained: vec![(4, 0)],
+                gaps: vec![4..=6, 8..=19],
+            }
+        );
+        assert_eq!(overlap_report(&[]), OverlapReport::default());
     }
 }
@@ -1,122 +1,1340 @@
+use std::fmt;
+use std::ops::Range;
+
 pub struct Day6;
 
 impl crate::Day for Day6 {
     fn run(input: String) -> crate::DayResult {
-        let lines = input
-            .lines()
-            .collect::<Vec<&str>>()
-            .iter()
-            .map(|line| line.chars().collect::<Vec<char>>())
-            .collect::<Vec<Vec<char>>>();
-
-        let mut problems = Vec::new();
-        let mut current_operator = Operator::Add; // Placeholder
-        let mut current_digit_grid = vec![Vec::new(); lines.len() - 1];
-        for column in 0..lines[0].len() {
-            let mut current_column = Vec::new();
-            for line in &lines {
-                current_column.push(line[column]);
-            }
-
-            if current_column.iter().filter(|c| **c != ' ').count() == 0 {
-                problems.push(CephalopodProblem::new(
-                    current_digit_grid.clone(),
-                    current_operator.clone(),
-                ));
-                current_digit_grid = vec![Vec::new(); lines.len() - 1];
-            } else {
-                for row in 0..(lines.len() - 1) {
-                    current_digit_grid[row].push(current_column[row]);
-                }
-                if current_column[lines.len() - 1] == '+' {
-                    current_operator = Operator::Add;
-                } else if current_column[lines.len() - 1] == '*' {
-                    current_operator = Operator::Multiply;
-                }
+        let scanner = ProblemScanner::new(input.as_bytes()).unwrap_or_else(|err| panic!("{err}"));
+
+        // Each problem goes into both totals as soon as it's read, none are kept
+        let mut totals = [0u64; 2];
+        for (index, problem) in scanner.enumerate() {
+            let problem = problem.unwrap_or_else(|err| panic!("{err}"));
+            for (total, reading) in totals
+                .iter_mut()
+                .zip([Reading::Horizontal, Reading::Vertical])
+            {
+                *total = add_to_total(*total, index, &problem, reading)
+                    .unwrap_or_else(|err| panic!("{err}"));
             }
         }
-        problems.push(CephalopodProblem::new(current_digit_grid, current_operator));
 
         crate::DayResult {
-            part_1: problems
-                .iter()
-                .map(|col| col.compute_horizontal())
-                .sum::<u64>(),
-            part_2: problems
-                .iter()
-                .map(|col| col.compute_vertical())
-                .sum::<u64>(),
+            part_1: totals[0],
+            part_2: totals[1],
+        }
+    }
+}
+
+/// Rows and columns count from 0, columns in characters after tabs have been expanded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorksheetError {
+    Empty,
+    InvalidCharacter {
+        symbol: char,
+        row: usize,
+        column: usize,
+    },
+    UnknownOperator {
+        symbol: char,
+        row: usize,
+        column: usize,
+    },
+    MissingOperator {
+        columns: Range<usize>,
+    },
+    MultipleOperators {
+        columns: Range<usize>,
+    },
+    /// A row or column of a problem with no digits in it
+    MissingNumber {
+        row: usize,
+        column: usize,
+    },
+    /// Digits broken up by a space, at the first digit after the space
+    SplitNumber {
+        row: usize,
+        column: usize,
+    },
+    NumberTooLarge {
+        row: usize,
+        column: usize,
+    },
+    /// A problem built from an empty list of numbers
+    NoOperands,
+    /// Problem `problem` of a list being written has no numbers in that reading
+    MissingReading {
+        problem: usize,
+        reading: Reading,
+    },
+    /// Problem `problem` of a list being written horizontally has a
+    /// different number of operands to the first, so they can't share rows
+    OperandCountMismatch {
+        problem: usize,
+    },
+}
+
+impl fmt::Display for WorksheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorksheetError::Empty => write!(f, "Worksheet has no numbers in it"),
+            WorksheetError::InvalidCharacter {
+                symbol,
+                row,
+                column,
+            } => {
+                write!(
+                    f,
+                    "Invalid character {symbol:?} at row {row}, column {column}"
+                )
+            }
+            WorksheetError::UnknownOperator {
+                symbol,
+                row,
+                column,
+            } => {
+                write!(
+                    f,
+                    "Unknown operator {symbol:?} at row {row}, column {column}"
+                )
+            }
+            WorksheetError::MissingOperator { columns } => {
+                write!(f, "No operator for the problem in columns {columns:?}")
+            }
+            WorksheetError::MultipleOperators { columns } => {
+                write!(
+                    f,
+                    "More than one operator for the problem in columns {columns:?}"
+                )
+            }
+            WorksheetError::MissingNumber { row, column } => {
+                write!(f, "Missing number starting at row {row}, column {column}")
+            }
+            WorksheetError::SplitNumber { row, column } => {
+                write!(f, "Number split by a space at row {row}, column {column}")
+            }
+            WorksheetError::NumberTooLarge { row, column } => {
+                write!(f, "Number too large at row {row}, column {column}")
+            }
+            WorksheetError::NoOperands => write!(f, "Problem has no numbers"),
+            WorksheetError::MissingReading { problem, reading } => {
+                write!(f, "Problem {problem} can't be read {reading}ly")
+            }
+            WorksheetError::OperandCountMismatch { problem } => {
+                write!(
+                    f,
+                    "Problem {problem} has a different number of operands to problem 0"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorksheetError {}
+
+const TAB_WIDTH: usize = 8;
+
+pub fn parse_worksheet(input: &str) -> Result<Vec<CephalopodProblem>, WorksheetError> {
+    ProblemScanner::new(input.as_bytes())?.collect()
+}
+
+/// Like parse_worksheet, but only `reading` has to form numbers
+pub fn parse_worksheet_as(
+    input: &str,
+    reading: Reading,
+) -> Result<Vec<CephalopodProblem>, WorksheetError> {
+    ProblemScanner::for_reading(input.as_bytes(), reading)?.collect()
+}
+
+/// Reads a worksheet column by column straight from its bytes, yielding each
+/// problem as soon as the blank column after it is reached. Only a cursor
+/// per row and the current problem's numbers are held, so memory doesn't
+/// grow with the width or the number of problems.
+///
+/// Tabs expand to the next multiple of TAB_WIDTH, short lines count as
+/// padded with spaces and trailing blank lines are dropped, the last
+/// remaining line holds the operators. Problems are separated by runs of
+/// columns blank in every row, and each needs exactly one operator somewhere
+/// under its columns. Stops after the first error
+pub struct ProblemScanner<'a> {
+    digit_rows: Vec<RowCursor<'a>>,
+    operator_row: RowCursor<'a>,
+    reading: Option<Reading>, // The one reading that has to work, or None for both
+    column: usize,            // The next column to be read
+    cells: Vec<char>,
+    failed: bool,
+}
+
+impl<'a> ProblemScanner<'a> {
+    /// Every problem has to read both ways round
+    pub fn new(input: &'a [u8]) -> Result<Self, WorksheetError> {
+        Self::with_reading(input, None)
+    }
+
+    /// Only `reading` has to form numbers. Problems still get the other
+    /// reading when their digits happen to form numbers that way too
+    pub fn for_reading(input: &'a [u8], reading: Reading) -> Result<Self, WorksheetError> {
+        Self::with_reading(input, Some(reading))
+    }
+
+    fn with_reading(input: &'a [u8], reading: Option<Reading>) -> Result<Self, WorksheetError> {
+        let mut rows: Vec<&[u8]> = input
+            .split(|&byte| byte == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .collect();
+        while rows
+            .last()
+            .is_some_and(|row| row.iter().all(u8::is_ascii_whitespace))
+        {
+            rows.pop();
+        }
+
+        let (&operator_row, digit_rows) = rows.split_last().ok_or(WorksheetError::Empty)?;
+        if digit_rows.is_empty() {
+            return Err(WorksheetError::Empty);
+        }
+
+        Ok(ProblemScanner {
+            digit_rows: digit_rows.iter().map(|&row| RowCursor::new(row)).collect(),
+            operator_row: RowCursor::new(operator_row),
+            reading,
+            column: 0,
+            cells: Vec::with_capacity(digit_rows.len()),
+            failed: false,
+        })
+    }
+
+    // Reads the next column's digit cells into `cells` and returns its operator
+    // cell, or None once every row has run out
+    fn next_column(&mut self) -> Option<char> {
+        if self.operator_row.is_done() && self.digit_rows.iter().all(RowCursor::is_done) {
+            return None;
+        }
+
+        self.cells.clear();
+        self.cells
+            .extend(self.digit_rows.iter_mut().map(RowCursor::next_cell));
+        self.column += 1;
+        Some(self.operator_row.next_cell())
+    }
+
+    fn scan_problem(&mut self, first_operator: char) -> Result<CephalopodProblem, WorksheetError> {
+        let start = self.column - 1;
+        let required = |reading| self.reading.is_none_or(|required| required == reading);
+        let (horizontal_required, vertical_required) =
+            (required(Reading::Horizontal), required(Reading::Vertical));
+
+        // A reading that doesn't have to work is dropped (None) at its first error
+        let mut rows = vec![Some(NumberReader::default()); self.digit_rows.len()];
+        let mut vertical = Vec::new();
+        let mut operators = Vec::new();
+
+        let mut symbol = Some(first_operator);
+        while let Some(operator_cell) = symbol {
+            let x = self.column - 1;
+            if operator_cell == ' ' && self.cells.iter().all(|&cell| cell == ' ') {
+                break;
+            }
+
+            let mut column = Some(NumberReader::default());
+            for (y, &cell) in self.cells.iter().enumerate() {
+                if cell != ' ' && !cell.is_ascii_digit() {
+                    return Err(WorksheetError::InvalidCharacter {
+                        symbol: cell,
+                        row: y,
+                        column: x,
+                    });
+                }
+                push_digit(&mut rows[y], cell as u8, y, x, horizontal_required)?;
+                push_digit(&mut column, cell as u8, y, x, vertical_required)?;
+            }
+            vertical.push(finish_number(column, 0, x, vertical_required)?);
+
+            if operator_cell != ' ' {
+                operators.push((x, operator_cell));
+            }
+            symbol = self.next_column();
+        }
+
+        // The blank column that ended the problem has been read, unless the input ran out first
+        let end = if symbol.is_some() {
+            self.column - 1
+        } else {
+            self.column
+        };
+        let columns = start..end;
+        let operator = match operators[..] {
+            [(x, symbol)] => {
+                Operator::from_symbol(symbol).ok_or(WorksheetError::UnknownOperator {
+                    symbol,
+                    row: self.digit_rows.len(),
+                    column: x,
+                })?
+            }
+            [] => return Err(WorksheetError::MissingOperator { columns }),
+            _ => return Err(WorksheetError::MultipleOperators { columns }),
+        };
+        let horizontal = rows
+            .into_iter()
+            .enumerate()
+            .map(|(y, row)| finish_number(row, y, start, horizontal_required))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CephalopodProblem {
+            horizontal: horizontal.into_iter().collect(),
+            vertical: vertical.into_iter().collect(),
+            operator,
+        })
+    }
+}
+
+// Adds a digit to a number that's still readable, passing the error on if
+// the reading is required and otherwise marking the number unreadable
+fn push_digit(
+    number: &mut Option<NumberReader>,
+    cell: u8,
+    row: usize,
+    column: usize,
+    required: bool,
+) -> Result<(), WorksheetError> {
+    if let Some(reader) = number
+        && let Err(err) = reader.push(cell, row, column)
+    {
+        if required {
+            return Err(err);
+        }
+        *number = None;
+    }
+    Ok(())
+}
+
+fn finish_number(
+    number: Option<NumberReader>,
+    row: usize,
+    column: usize,
+    required: bool,
+) -> Result<Option<u64>, WorksheetError> {
+    match number.map(|number| number.finish(row, column)) {
+        Some(Ok(number)) => Ok(Some(number)),
+        Some(Err(err)) if required => Err(err),
+        _ => Ok(None),
+    }
+}
+
+impl Iterator for ProblemScanner<'_> {
+    type Item = Result<CephalopodProblem, WorksheetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        // Skip blank columns up to the start of the next problem
+        loop {
+            let operator_cell = self.next_column()?;
+            if operator_cell != ' ' || self.cells.iter().any(|&cell| cell != ' ') {
+                let problem = self.scan_problem(operator_cell);
+                self.failed = problem.is_err();
+                return Some(problem);
+            }
         }
     }
 }
 
-#[derive(Debug)]
-struct CephalopodProblem {
-    horizontal: Vec<u64>,
-    vertical: Vec<u64>,
+// Walks one line a character at a time, expanding tabs and turning any
+// other whitespace into a space
+struct RowCursor<'a> {
+    line: &'a [u8],
+    position: usize, // Byte offset of the next character
+    column: usize,
+    tab_spaces: usize, // Spaces left to hand out for the last tab
+}
+
+impl<'a> RowCursor<'a> {
+    fn new(line: &'a [u8]) -> Self {
+        RowCursor {
+            line,
+            position: 0,
+            column: 0,
+            tab_spaces: 0,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.tab_spaces == 0 && self.position >= self.line.len()
+    }
+
+    // A space once the line has run out
+    fn next_cell(&mut self) -> char {
+        let column = self.column;
+        self.column += 1;
+        if self.tab_spaces > 0 {
+            self.tab_spaces -= 1;
+            return ' ';
+        }
+        let Some(rest) = self
+            .line
+            .get(self.position..)
+            .filter(|rest| !rest.is_empty())
+        else {
+            return ' ';
+        };
+
+        let (c, len) = decode_char(rest);
+        self.position += len;
+        match c {
+            '\t' => {
+                self.tab_spaces = TAB_WIDTH - 1 - column % TAB_WIDTH;
+                ' '
+            }
+            c if c.is_whitespace() => ' ',
+            c => c,
+        }
+    }
+}
+
+// The first character of some UTF-8 and its length in bytes.
+// Invalid bytes come out one at a time as U+FFFD
+fn decode_char(bytes: &[u8]) -> (char, usize) {
+    let len = match bytes[0] {
+        byte if byte < 0x80 => return (byte as char, 1),
+        byte if byte >= 0xf0 => 4,
+        byte if byte >= 0xe0 => 3,
+        _ => 2,
+    };
+    bytes
+        .get(..len)
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+        .and_then(|text| text.chars().next())
+        .map_or((char::REPLACEMENT_CHARACTER, 1), |c| (c, len))
+}
+
+// One number built up a digit at a time, padded with spaces on either side
+#[derive(Debug, Clone, Copy, Default)]
+struct NumberReader {
+    number: Option<u64>,
+    ended: bool,
+}
+
+impl NumberReader {
+    fn push(&mut self, cell: u8, row: usize, column: usize) -> Result<(), WorksheetError> {
+        match (cell, self.number) {
+            (b' ', None) => {}
+            (b' ', Some(_)) => self.ended = true,
+            (_, Some(_)) if self.ended => {
+                return Err(WorksheetError::SplitNumber { row, column });
+            }
+            (digit, number) => {
+                self.number = Some(
+                    number
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add((digit - b'0') as u64))
+                        .ok_or(WorksheetError::NumberTooLarge { row, column })?,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // (row, column) is where the number would have started
+    fn finish(self, row: usize, column: usize) -> Result<u64, WorksheetError> {
+        self.number
+            .ok_or(WorksheetError::MissingNumber { row, column })
+    }
+}
+
+/// A problem read by parse_worksheet has numbers both ways round. One read
+/// by parse_worksheet_as, or built from a list of numbers, only has the
+/// other reading if its digits happen to form numbers that way too
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CephalopodProblem {
+    horizontal: Option<Vec<u64>>,
+    vertical: Option<Vec<u64>>,
     operator: Operator,
 }
 
 impl CephalopodProblem {
-    fn new(digit_grid: Vec<Vec<char>>, operator: Operator) -> Self {
-        let horizontal = Self::parse_horizontal(digit_grid.clone());
-        let vertical = Self::parse_vertical(digit_grid);
+    pub fn new(
+        operator: Operator,
+        reading: Reading,
+        operands: Vec<u64>,
+    ) -> Result<Self, WorksheetError> {
+        if operands.is_empty() {
+            return Err(WorksheetError::NoOperands);
+        }
+
+        let layout = WorksheetWriter::new(reading).layout(&operands, None);
+        let (horizontal, vertical) = match reading {
+            Reading::Horizontal => (Some(operands), read_layout(&layout, Reading::Vertical)),
+            Reading::Vertical => (read_layout(&layout, Reading::Horizontal), Some(operands)),
+        };
 
-        CephalopodProblem {
+        Ok(CephalopodProblem {
             horizontal,
             vertical,
             operator,
+        })
+    }
+
+    pub fn operator(&self) -> Operator {
+        self.operator
+    }
+
+    /// None if the problem has no numbers that way round
+    pub fn operands(&self, reading: Reading) -> Option<&[u64]> {
+        match reading {
+            Reading::Horizontal => self.horizontal.as_deref(),
+            Reading::Vertical => self.vertical.as_deref(),
         }
     }
 
-    fn parse_vertical(digit_grid: Vec<Vec<char>>) -> Vec<u64> {
-        let mut result = Vec::new();
-        for col_idx in 0..digit_grid[0].len() {
-            let mut col_chars = Vec::new();
-            for row in &digit_grid {
-                col_chars.push(row[col_idx]);
+    /// Panics if the problem has no numbers that way round
+    pub fn evaluate<T: Number>(&self, reading: Reading) -> Result<T, ArithmeticError> {
+        let operands = self
+            .operands(reading)
+            .unwrap_or_else(|| panic!("{self:?} can't be read {reading}ly"));
+        self.operator.evaluate(operands)
+    }
+
+    /// Panics if the result doesn't fit in a u64
+    pub fn compute_horizontal(&self) -> u64 {
+        self.evaluate(Reading::Horizontal)
+            .unwrap_or_else(|err| panic!("{err} in {self:?}"))
+    }
+
+    /// Panics if the result doesn't fit in a u64
+    pub fn compute_vertical(&self) -> u64 {
+        self.evaluate(Reading::Vertical)
+            .unwrap_or_else(|err| panic!("{err} in {self:?}"))
+    }
+}
+
+// The numbers in a block of digit rows read one way, None if some row or
+// column isn't exactly one number
+fn read_layout(rows: &[Vec<char>], reading: Reading) -> Option<Vec<u64>> {
+    let width = rows.first().map_or(0, Vec::len);
+    let lines: Vec<Vec<char>> = match reading {
+        Reading::Horizontal => rows.to_vec(),
+        Reading::Vertical => (0..width)
+            .map(|x| rows.iter().map(|row| row[x]).collect())
+            .collect(),
+    };
+
+    lines
+        .iter()
+        .map(|line| {
+            let mut number = NumberReader::default();
+            for &cell in line {
+                number.push(cell as u8, 0, 0).ok()?;
             }
-            let col_number = col_chars
+            number.finish(0, 0).ok()
+        })
+        .collect()
+}
+
+/// Renders problems back into worksheet text, laying out each problem's
+/// numbers for one reading. Horizontal numbers are right aligned and
+/// vertical ones end on the bottom row, with one blank column between problems.
+///
+/// The text always parses with parse_worksheet_as in the writer's reading.
+/// The other reading isn't kept: it's whatever the laid out digits form,
+/// and vertical problems shorter than the tallest one are padded above
+/// with the fill, so it can differ from the problem's own or be missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorksheetWriter {
+    reading: Reading,
+    fill: char,
+}
+
+impl WorksheetWriter {
+    pub fn new(reading: Reading) -> Self {
+        WorksheetWriter { reading, fill: ' ' }
+    }
+
+    pub fn with_fill(self, fill: char) -> Self {
+        assert!(fill == ' ' || fill == '0', "Fill must be ' ' or '0'");
+        WorksheetWriter { fill, ..self }
+    }
+
+    pub fn write(&self, problems: &[CephalopodProblem]) -> Result<String, WorksheetError> {
+        let operands = problems
+            .iter()
+            .enumerate()
+            .map(|(index, problem)| {
+                problem
+                    .operands(self.reading)
+                    .ok_or(WorksheetError::MissingReading {
+                        problem: index,
+                        reading: self.reading,
+                    })
+            })
+            .collect::<Result<Vec<&[u64]>, _>>()?;
+
+        let height = match self.reading {
+            Reading::Horizontal => None,
+            Reading::Vertical => operands
                 .iter()
-                .collect::<String>()
-                .trim()
-                .parse::<u64>()
-                .unwrap();
-            result.push(col_number);
+                .flat_map(|operands| operands.iter())
+                .map(|&operand| num_digits(operand))
+                .max(),
+        };
+        let blocks: Vec<Vec<Vec<char>>> = operands
+            .iter()
+            .zip(problems)
+            .map(|(operands, problem)| {
+                let mut block = self.layout(operands, height);
+                let mut operator_row = vec![' '; block[0].len()];
+                operator_row[0] = problem.operator.symbol();
+                block.push(operator_row);
+                block
+            })
+            .collect();
+
+        let Some(first) = blocks.first() else {
+            return Err(WorksheetError::Empty);
+        };
+        if let Some(index) = blocks.iter().position(|block| block.len() != first.len()) {
+            return Err(WorksheetError::OperandCountMismatch { problem: index });
         }
-        result
+
+        let mut text = String::new();
+        for y in 0..first.len() {
+            let mut line = String::new();
+            for (i, block) in blocks.iter().enumerate() {
+                if i > 0 {
+                    line.push(' ');
+                }
+                line.extend(&block[y]);
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+
+        Ok(text)
     }
 
-    fn parse_horizontal(digit_grid: Vec<Vec<char>>) -> Vec<u64> {
-        digit_grid
+    // One problem's digit rows, never empty for a non-empty list of operands.
+    // Vertical blocks are `height` digits tall, or as tall as their longest
+    // operand
+    fn layout(&self, operands: &[u64], height: Option<usize>) -> Vec<Vec<char>> {
+        let digits: Vec<Vec<char>> = operands
             .iter()
-            .map(|row| {
-                row.iter()
-                    .collect::<String>()
-                    .trim()
-                    .parse::<u64>()
-                    .unwrap()
-            })
-            .collect()
+            .map(|operand| operand.to_string().chars().collect())
+            .collect();
+        let longest = digits.iter().map(Vec::len).max().unwrap_or(0);
+
+        match self.reading {
+            Reading::Horizontal => digits
+                .iter()
+                .map(|number| {
+                    let mut row = vec![self.fill; longest - number.len()];
+                    row.extend(number);
+                    row
+                })
+                .collect(),
+            Reading::Vertical => {
+                let height = height.unwrap_or(longest);
+                (0..height)
+                    .map(|y| {
+                        digits
+                            .iter()
+                            .map(|number| {
+                                // Padding goes above, where a '0' fill is a leading zero
+                                (y + number.len())
+                                    .checked_sub(height)
+                                    .map_or(self.fill, |i| number[i])
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
     }
+}
 
-    fn compute_horizontal(&self) -> u64 {
-        match self.operator {
-            Operator::Add => self.horizontal.iter().sum(),
-            Operator::Multiply => self.horizontal.iter().product(),
+fn num_digits(n: u64) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+/// Which way the numbers in a problem are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reading {
+    Horizontal, // One number per row, how humans write them
+    Vertical,   // One number per column, most significant digit at the top
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reading::Horizontal => write!(f, "horizontal"),
+            Reading::Vertical => write!(f, "vertical"),
         }
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvaluationError {
+    /// A single problem has no result, `index` being its position in the list
+    Problem {
+        index: usize,
+        reading: Reading,
+        operator: Operator,
+        operands: Vec<u64>,
+        cause: ArithmeticError,
+    },
+    /// Adding the result of problem `index` overflowed the running total
+    TotalOverflow { index: usize, reading: Reading },
+    /// Problem `index` has no numbers that way round
+    Unreadable { index: usize, reading: Reading },
+}
 
-    fn compute_vertical(&self) -> u64 {
-        match self.operator {
-            Operator::Add => self.vertical.iter().sum(),
-            Operator::Multiply => self.vertical.iter().product(),
+impl fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvaluationError::Problem {
+                index,
+                reading,
+                operator,
+                operands,
+                cause,
+            } => {
+                write!(f, "{cause} in problem {index} ({reading}): ")?;
+                for (i, operand) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " {operator} ")?;
+                    }
+                    write!(f, "{operand}")?;
+                }
+                Ok(())
+            }
+            EvaluationError::Unreadable { index, reading } => {
+                write!(f, "Problem {index} can't be read {reading}ly")
+            }
+            EvaluationError::TotalOverflow { index, reading } => {
+                write!(
+                    f,
+                    "Grand total ({reading}) overflowed adding problem {index}"
+                )
+            }
         }
     }
 }
 
-#[derive(Debug, Clone)]
-enum Operator {
+impl std::error::Error for EvaluationError {}
+
+// Sum of every problem's result, checked all the way through. Evaluating in
+// u128 gives headroom for answers that don't fit in a u64:
+//   grand_total::<u128>(&problems, Reading::Vertical)
+pub fn grand_total<T: Number>(
+    problems: &[CephalopodProblem],
+    reading: Reading,
+) -> Result<T, EvaluationError> {
+    problems
+        .iter()
+        .enumerate()
+        .try_fold(T::from(0), |total, (index, problem)| {
+            add_to_total(total, index, problem, reading)
+        })
+}
+
+/// One step of a grand total, for callers that see problems one at a time
+pub fn add_to_total<T: Number>(
+    total: T,
+    index: usize,
+    problem: &CephalopodProblem,
+    reading: Reading,
+) -> Result<T, EvaluationError> {
+    let operands = problem
+        .operands(reading)
+        .ok_or(EvaluationError::Unreadable { index, reading })?;
+    let result =
+        problem
+            .operator
+            .evaluate::<T>(operands)
+            .map_err(|cause| EvaluationError::Problem {
+                index,
+                reading,
+                operator: problem.operator,
+                operands: operands.to_vec(),
+                cause,
+            })?;
+    total
+        .checked_add(result)
+        .ok_or(EvaluationError::TotalOverflow { index, reading })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    Overflow, // Including subtraction below zero
+    DivisionByZero,
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticError::Overflow => write!(f, "Overflow"),
+            ArithmeticError::DivisionByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+/// Unsigned integer types problems can be evaluated in
+pub trait Number: Copy + Ord + From<u64> {
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+    /// 10 to the power of the number of decimal digits in self
+    fn digit_shift(self) -> Option<Self>;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    self.checked_add(rhs)
+                }
+
+                fn checked_sub(self, rhs: Self) -> Option<Self> {
+                    self.checked_sub(rhs)
+                }
+
+                fn checked_mul(self, rhs: Self) -> Option<Self> {
+                    self.checked_mul(rhs)
+                }
+
+                fn checked_div(self, rhs: Self) -> Option<Self> {
+                    self.checked_div(rhs)
+                }
+
+                fn digit_shift(self) -> Option<Self> {
+                    (10 as $t).checked_pow(self.checked_ilog10().unwrap_or(0) + 1)
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u64, u128);
+
+/// Operators apply left to right, so 9 5 2 under `-` is (9 - 5) - 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
     Add,
+    Subtract,
     Multiply,
+    Divide, // Integer division, rounding down
+    Min,
+    Max,
+    Concatenate, // Decimal digits of each operand one after the other
+}
+
+impl Operator {
+    pub const ALL: [Operator; 7] = [
+        Operator::Add,
+        Operator::Subtract,
+        Operator::Multiply,
+        Operator::Divide,
+        Operator::Min,
+        Operator::Max,
+        Operator::Concatenate,
+    ];
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|operator| operator.symbol() == symbol)
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            Operator::Add => '+',
+            Operator::Subtract => '-',
+            Operator::Multiply => '*',
+            Operator::Divide => '/',
+            Operator::Min => '<',
+            Operator::Max => '>',
+            Operator::Concatenate => '|',
+        }
+    }
+
+    pub fn apply<T: Number>(self, lhs: T, rhs: T) -> Result<T, ArithmeticError> {
+        let result = match self {
+            Operator::Add => lhs.checked_add(rhs),
+            Operator::Subtract => lhs.checked_sub(rhs),
+            Operator::Multiply => lhs.checked_mul(rhs),
+            Operator::Divide => {
+                return lhs.checked_div(rhs).ok_or(ArithmeticError::DivisionByZero);
+            }
+            Operator::Min => Some(lhs.min(rhs)),
+            Operator::Max => Some(lhs.max(rhs)),
+            Operator::Concatenate => rhs
+                .digit_shift()
+                .and_then(|shift| lhs.checked_mul(shift))
+                .and_then(|shifted| shifted.checked_add(rhs)),
+        };
+        result.ok_or(ArithmeticError::Overflow)
+    }
+
+    /// Panics if there are no operands
+    pub fn evaluate<T: Number>(self, operands: &[u64]) -> Result<T, ArithmeticError> {
+        let (&first, rest) = operands.split_first().expect("No operands to evaluate");
+        rest.iter().try_fold(T::from(first), |acc, &operand| {
+            self.apply(acc, T::from(operand))
+        })
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::XorShift;
+
+    const EXAMPLE: &str = "123 328  51 64 \n 45 64  387 23 \n  6 98  215 314\n*   +   *   +  \n";
+
+    const READINGS: [Reading; 2] = [Reading::Horizontal, Reading::Vertical];
+
+    fn assert_same_problems(
+        left: &[CephalopodProblem],
+        right: &[CephalopodProblem],
+        reading: Reading,
+    ) {
+        assert_eq!(left.len(), right.len());
+        for (left, right) in left.iter().zip(right) {
+            assert_eq!(left.operator(), right.operator());
+            assert_eq!(left.operands(reading), right.operands(reading));
+        }
+    }
+
+    #[test]
+    fn example_totals() {
+        let problems = parse_worksheet(EXAMPLE).unwrap();
+        assert_eq!(
+            grand_total::<u64>(&problems, Reading::Horizontal),
+            Ok(4277556)
+        );
+        assert_eq!(
+            grand_total::<u64>(&problems, Reading::Vertical),
+            Ok(3263827)
+        );
+    }
+
+    #[test]
+    fn example_round_trips() {
+        let problems = parse_worksheet(EXAMPLE).unwrap();
+        for reading in READINGS {
+            for fill in [' ', '0'] {
+                let text = WorksheetWriter::new(reading)
+                    .with_fill(fill)
+                    .write(&problems)
+                    .unwrap();
+                assert_same_problems(&problems, &parse_worksheet(&text).unwrap(), reading);
+            }
+        }
+    }
+
+    #[test]
+    fn random_problems_round_trip() {
+        let mut random = XorShift::new(0x9e3779b97f4a7c15);
+        let mut next = |bound: u64| random.below(bound);
+
+        for _ in 0..500 {
+            let reading = READINGS[next(2) as usize];
+            let num_operands = 1 + next(4) as usize;
+            let problems: Vec<CephalopodProblem> = (0..1 + next(5))
+                .map(|_| {
+                    let operands = (0..num_operands)
+                        .map(|_| {
+                            let num_digits = 1 + next(6) as u32;
+                            next(10u64.pow(num_digits))
+                        })
+                        .collect();
+                    let operator = Operator::ALL[next(Operator::ALL.len() as u64) as usize];
+                    CephalopodProblem::new(operator, reading, operands).unwrap()
+                })
+                .collect();
+
+            for fill in [' ', '0'] {
+                let text = WorksheetWriter::new(reading)
+                    .with_fill(fill)
+                    .write(&problems)
+                    .unwrap();
+                let parsed = parse_worksheet_as(&text, reading).unwrap();
+                assert_same_problems(&problems, &parsed, reading);
+            }
+        }
+    }
+
+    #[test]
+    fn new_problem_without_other_reading() {
+        let problem =
+            CephalopodProblem::new(Operator::Add, Reading::Horizontal, vec![10, 5, 10]).unwrap();
+        assert_eq!(
+            problem.operands(Reading::Horizontal),
+            Some(&[10, 5, 10][..])
+        );
+        assert_eq!(problem.operands(Reading::Vertical), None);
+        assert_eq!(problem.compute_horizontal(), 25);
+
+        let text = WorksheetWriter::new(Reading::Horizontal)
+            .with_fill('0')
+            .write(std::slice::from_ref(&problem))
+            .unwrap();
+        assert_eq!(text, "10\n05\n10\n+\n");
+        assert_eq!(
+            WorksheetWriter::new(Reading::Vertical).write(&[problem]),
+            Err(WorksheetError::MissingReading {
+                problem: 0,
+                reading: Reading::Vertical
+            })
+        );
+    }
+
+    #[test]
+    fn new_problem_with_both_readings() {
+        let problem =
+            CephalopodProblem::new(Operator::Multiply, Reading::Horizontal, vec![123, 45, 6])
+                .unwrap();
+        assert_eq!(problem.operands(Reading::Vertical), Some(&[1, 24, 356][..]));
+    }
+
+    #[test]
+    fn new_problem_needs_operands() {
+        assert_eq!(
+            CephalopodProblem::new(Operator::Add, Reading::Vertical, Vec::new()),
+            Err(WorksheetError::NoOperands)
+        );
+    }
+
+    #[test]
+    fn fill_only_changes_the_other_reading() {
+        let problem =
+            CephalopodProblem::new(Operator::Add, Reading::Vertical, vec![12, 5, 34]).unwrap();
+        let spaces = WorksheetWriter::new(Reading::Vertical)
+            .write(std::slice::from_ref(&problem))
+            .unwrap();
+        assert_eq!(spaces, "1 3\n254\n+\n");
+        let zeros = WorksheetWriter::new(Reading::Vertical)
+            .with_fill('0')
+            .write(std::slice::from_ref(&problem))
+            .unwrap();
+        assert_eq!(zeros, "103\n254\n+\n");
+
+        for text in [&spaces, &zeros] {
+            let parsed = parse_worksheet_as(text, Reading::Vertical).unwrap();
+            assert_eq!(
+                parsed[0].operands(Reading::Vertical),
+                Some(&[12, 5, 34][..])
+            );
+        }
+        // "1 3" is split, so only the zero filled text reads across
+        assert_eq!(
+            parse_worksheet(&spaces),
+            Err(WorksheetError::SplitNumber { row: 0, column: 2 })
+        );
+        let parsed = parse_worksheet_as(&spaces, Reading::Vertical).unwrap();
+        assert_eq!(parsed[0].operands(Reading::Horizontal), None);
+        let parsed = parse_worksheet(&zeros).unwrap();
+        assert_eq!(
+            parsed[0].operands(Reading::Horizontal),
+            Some(&[103, 254][..])
+        );
+    }
+
+    #[test]
+    fn vertical_problems_of_mixed_heights() {
+        let problems = [
+            CephalopodProblem::new(Operator::Add, Reading::Vertical, vec![1]).unwrap(),
+            CephalopodProblem::new(Operator::Multiply, Reading::Vertical, vec![123]).unwrap(),
+            CephalopodProblem::new(Operator::Add, Reading::Vertical, vec![45, 6789, 0]).unwrap(),
+        ];
+        for fill in [' ', '0'] {
+            let text = WorksheetWriter::new(Reading::Vertical)
+                .with_fill(fill)
+                .write(&problems)
+                .unwrap();
+            let parsed = parse_worksheet_as(&text, Reading::Vertical).unwrap();
+            assert_same_problems(&problems, &parsed, Reading::Vertical);
+        }
+        assert_eq!(
+            WorksheetWriter::new(Reading::Vertical).write(&problems),
+            Ok("     6\n  1  7\n  2 48\n1 3 590\n+ * +\n".to_string())
+        );
+    }
+
+    #[test]
+    fn horizontal_problems_of_mixed_widths() {
+        let problems = [
+            CephalopodProblem::new(Operator::Add, Reading::Horizontal, vec![1, 22]).unwrap(),
+            CephalopodProblem::new(Operator::Multiply, Reading::Horizontal, vec![333, 4]).unwrap(),
+        ];
+        for fill in [' ', '0'] {
+            let text = WorksheetWriter::new(Reading::Horizontal)
+                .with_fill(fill)
+                .write(&problems)
+                .unwrap();
+            let parsed = parse_worksheet_as(&text, Reading::Horizontal).unwrap();
+            assert_same_problems(&problems, &parsed, Reading::Horizontal);
+        }
+    }
+
+    #[test]
+    fn mismatched_operand_counts() {
+        let problems = [
+            CephalopodProblem::new(Operator::Add, Reading::Horizontal, vec![1, 2]).unwrap(),
+            CephalopodProblem::new(Operator::Add, Reading::Horizontal, vec![1, 2, 3]).unwrap(),
+        ];
+        assert_eq!(
+            WorksheetWriter::new(Reading::Horizontal).write(&problems),
+            Err(WorksheetError::OperandCountMismatch { problem: 1 })
+        );
+    }
+
+    #[test]
+    fn non_ascii_characters_are_decoded() {
+        assert_eq!(
+            parse_worksheet("1é\n+ \n").unwrap_err(),
+            WorksheetError::InvalidCharacter {
+                symbol: 'é',
+                row: 0,
+                column: 1
+            }
+        );
+        // Columns count characters, so the '?' is in column 2 not 3
+        assert_eq!(
+            parse_worksheet("é 1\n  ?\n").unwrap_err(),
+            WorksheetError::InvalidCharacter {
+                symbol: 'é',
+                row: 0,
+                column: 0
+            }
+        );
+        assert_eq!(
+            parse_worksheet("1 1\n+ ¿\n").unwrap_err(),
+            WorksheetError::UnknownOperator {
+                symbol: '¿',
+                row: 1,
+                column: 2
+            }
+        );
+    }
+
+    #[test]
+    fn scanner_streams_problems() {
+        let mut scanner = ProblemScanner::new(EXAMPLE.as_bytes()).unwrap();
+        let first = scanner.next().unwrap().unwrap();
+        assert_eq!(first.operands(Reading::Horizontal), Some(&[123, 45, 6][..]));
+        assert_eq!(scanner.count(), 3);
+    }
+
+    fn horizontal_operands(input: &str) -> Vec<(Operator, Vec<u64>)> {
+        parse_worksheet(input)
+            .unwrap()
+            .iter()
+            .map(|problem| {
+                let operands = problem.operands(Reading::Horizontal).unwrap();
+                (problem.operator(), operands.to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_number() {
+        assert_eq!(
+            parse_worksheet("1 2\n345\n+  \n").unwrap_err(),
+            WorksheetError::SplitNumber { row: 0, column: 2 }
+        );
+    }
+
+    #[test]
+    fn missing_operator() {
+        assert_eq!(
+            parse_worksheet("12 3\n45 6\n   +\n").unwrap_err(),
+            WorksheetError::MissingOperator { columns: 0..2 }
+        );
+    }
+
+    #[test]
+    fn multiple_operators() {
+        assert_eq!(
+            parse_worksheet("123\n456\n+ *\n").unwrap_err(),
+            WorksheetError::MultipleOperators { columns: 0..3 }
+        );
+    }
+
+    #[test]
+    fn unknown_operator() {
+        assert_eq!(
+            parse_worksheet("12 34\n56 78\n+  %\n").unwrap_err(),
+            WorksheetError::UnknownOperator {
+                symbol: '%',
+                row: 2,
+                column: 3
+            }
+        );
+    }
+
+    #[test]
+    fn short_last_line() {
+        // No trailing newline, and the operator row stops before the digits do
+        assert_eq!(
+            horizontal_operands("64 123\n 3  45\n+  *"),
+            [
+                (Operator::Add, vec![64, 3]),
+                (Operator::Multiply, vec![123, 45])
+            ]
+        );
+    }
+
+    #[test]
+    fn tab_indented_line() {
+        // Tabs stop every 8 columns, wherever they start
+        assert_eq!(
+            horizontal_operands("\t12\n\t 3\n\t*\n"),
+            [(Operator::Multiply, vec![12, 3])]
+        );
+        assert_eq!(
+            horizontal_operands("1\t2\n3 \t4\n+\t*\n"),
+            [
+                (Operator::Add, vec![1, 3]),
+                (Operator::Multiply, vec![2, 4])
+            ]
+        );
+        assert_eq!(
+            parse_worksheet("1\t2\n3\t4\n+\t?\n").unwrap_err(),
+            WorksheetError::UnknownOperator {
+                symbol: '?',
+                row: 2,
+                column: 8
+            }
+        );
+    }
+
+    #[test]
+    fn crlf_input() {
+        let crlf = EXAMPLE.replace('\n', "\r\n");
+        let problems = parse_worksheet(&crlf).unwrap();
+        for reading in READINGS {
+            assert_same_problems(&problems, &parse_worksheet(EXAMPLE).unwrap(), reading);
+        }
+    }
+
+    #[test]
+    fn operator_symbols() {
+        for operator in Operator::ALL {
+            assert_eq!(Operator::from_symbol(operator.symbol()), Some(operator));
+        }
+        assert_eq!(Operator::from_symbol('?'), None);
+    }
+
+    #[test]
+    fn apply_operators() {
+        let apply =
+            |symbol, lhs: u64, rhs: u64| Operator::from_symbol(symbol).unwrap().apply(lhs, rhs);
+        assert_eq!(apply('-', 9, 5), Ok(4));
+        assert_eq!(apply('-', 5, 5), Ok(0));
+        assert_eq!(apply('-', 5, 9), Err(ArithmeticError::Overflow));
+        assert_eq!(apply('/', 7, 2), Ok(3));
+        assert_eq!(apply('/', 0, 2), Ok(0));
+        assert_eq!(apply('/', 7, 0), Err(ArithmeticError::DivisionByZero));
+        assert_eq!(apply('<', 7, 2), Ok(2));
+        assert_eq!(apply('>', 7, 2), Ok(7));
+        assert_eq!(apply('|', 12, 345), Ok(12345));
+        // 0 still has one digit to make room for
+        assert_eq!(apply('|', 12, 0), Ok(120));
+        assert_eq!(apply('|', 0, 7), Ok(7));
+        assert_eq!(apply('|', 0, 0), Ok(0));
+        assert_eq!(apply('|', 7, 10), Ok(710));
+        assert_eq!(apply('|', u64::MAX, 1), Err(ArithmeticError::Overflow));
+        assert_eq!(
+            Operator::Concatenate.apply::<u128>(u64::MAX.into(), 1),
+            Ok(184467440737095516151)
+        );
+    }
+
+    #[test]
+    fn evaluate_operators() {
+        let evaluate = |symbol, operands: &[u64]| {
+            Operator::from_symbol(symbol)
+                .unwrap()
+                .evaluate::<u64>(operands)
+        };
+        assert_eq!(evaluate('-', &[9, 5, 2]), Ok(2));
+        assert_eq!(evaluate('-', &[9, 5, 5]), Err(ArithmeticError::Overflow));
+        assert_eq!(evaluate('/', &[100, 5, 3]), Ok(6));
+        assert_eq!(
+            evaluate('/', &[100, 0, 3]),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(evaluate('<', &[5, 3, 8]), Ok(3));
+        assert_eq!(evaluate('>', &[5, 3, 8]), Ok(8));
+        assert_eq!(evaluate('|', &[1, 0, 23]), Ok(1023));
+        assert_eq!(evaluate('|', &[0, 0, 5]), Ok(5));
+        assert_eq!(evaluate('*', &[42]), Ok(42));
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        let problem = |operator, operands: &[u64]| {
+            CephalopodProblem::new(operator, Reading::Horizontal, operands.to_vec()).unwrap()
+        };
+        let problems = [
+            problem(Operator::Add, &[1, 2]),
+            problem(Operator::Multiply, &[u64::MAX, 2]),
+        ];
+
+        let err = grand_total::<u64>(&problems, Reading::Horizontal).unwrap_err();
+        assert_eq!(
+            err,
+            EvaluationError::Problem {
+                index: 1,
+                reading: Reading::Horizontal,
+                operator: Operator::Multiply,
+                operands: vec![u64::MAX, 2],
+                cause: ArithmeticError::Overflow,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Overflow in problem 1 (horizontal): 18446744073709551615 * 2"
+        );
+        assert_eq!(
+            grand_total::<u128>(&problems, Reading::Horizontal),
+            Ok(3 + 2 * u64::MAX as u128)
+        );
+
+        // Each problem fits on its own, the sum doesn't
+        let problems = [
+            problem(Operator::Add, &[u64::MAX - 1]),
+            problem(Operator::Max, &[0, 1]),
+            problem(Operator::Min, &[1, 3]),
+        ];
+        let err = grand_total::<u64>(&problems, Reading::Horizontal).unwrap_err();
+        assert_eq!(
+            err,
+            EvaluationError::TotalOverflow {
+                index: 2,
+                reading: Reading::Horizontal
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Grand total (horizontal) overflowed adding problem 2"
+        );
+        assert_eq!(
+            grand_total::<u128>(&problems, Reading::Horizontal),
+            Ok(u64::MAX as u128 + 1)
+        );
+    }
 }
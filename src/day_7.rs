@@ -1,104 +1,499 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::grid::{Grid, RaggedRow};
+
 pub struct Day7;
 
 impl crate::Day for Day7 {
     fn run(input: String) -> crate::DayResult {
-        let mut tachyon_manifold =
-            TachyonManifold::new(input.lines().map(|line| line.chars().collect()).collect());
+        let tachyon_manifold = TachyonManifold::new(&input).unwrap_or_else(|err| panic!("{err}"));
 
-        let (splits, timelines) = tachyon_manifold.run_beam();
+        let report = tachyon_manifold
+            .run_beam()
+            .unwrap_or_else(|err| panic!("{err}"));
 
         crate::DayResult {
-            part_1: splits,
-            part_2: timelines,
+            part_1: report.splits,
+            part_2: report.timelines,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifoldError {
+    InvalidCharacter {
+        symbol: char,
+        row: usize,
+        column: usize,
+    },
+    /// Row `row` has `length` cells where the first row has `expected`
+    RaggedRow {
+        row: usize,
+        length: usize,
+        expected: usize,
+    },
+    /// A beam can go round in a loop and still reach the bottom, so there
+    /// are infinitely many timelines. (x, y) is a cell on the loop
+    Loop { x: usize, y: usize },
+}
+
+impl fmt::Display for ManifoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifoldError::InvalidCharacter {
+                symbol,
+                row,
+                column,
+            } => write!(
+                f,
+                "Invalid character {symbol:?} at row {row}, column {column}"
+            ),
+            ManifoldError::RaggedRow {
+                row,
+                length,
+                expected,
+            } => write!(
+                f,
+                "Row {row} has {length} cells, expected {expected} like the first row"
+            ),
+            ManifoldError::Loop { x, y } => {
+                write!(
+                    f,
+                    "Beam loops through ({x}, {y}) with infinitely many timelines"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifoldError {}
+
+impl From<RaggedRow> for ManifoldError {
+    fn from(err: RaggedRow) -> Self {
+        ManifoldError::RaggedRow {
+            row: err.row,
+            length: err.length,
+            expected: err.expected,
         }
     }
 }
 
-struct TachyonManifold {
-    grid: Vec<Vec<GridSpace>>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeamReport {
+    /// Splitters hit by at least one beam
+    pub splits: u64,
+    /// Timelines leaving through the bottom edge, beams leaving any other
+    /// way are lost
+    pub timelines: u64,
+}
+
+pub struct TachyonManifold {
+    grid: Grid<GridSpace>,
 }
 
 impl TachyonManifold {
-    fn new(input: Vec<Vec<char>>) -> Self {
-        TachyonManifold {
-            grid: input
-                .into_iter()
-                .map(|row| {
-                    row.into_iter()
-                        .map(|c| match c {
-                            '.' => GridSpace::Empty,
-                            '^' => GridSpace::Splitter,
-                            'S' => GridSpace::TachyonBeam(1),
-                            _ => panic!("Invalid character in input"),
-                        })
-                        .collect()
-                })
-                .collect(),
-        }
-    }
-
-    // Runs the tachyon beam through the manifold, returning the number of splits and timelines.
-    fn run_beam(&mut self) -> (u64, u64) {
-        let mut num_splits = 0;
-        for i in 0..(self.grid.len() - 1) {
-            let (first, second) = self.grid.split_at_mut(i + 1);
-            num_splits += Self::process_row(&first[i], &mut second[0]);
+    pub fn new(input: &str) -> Result<Self, ManifoldError> {
+        let grid = Grid::try_parse(input, |c| GridSpace::from_symbol(c).ok_or(c))?;
+        if let Some((x, y, Err(symbol))) = grid.iter().find(|(_, _, space)| space.is_err()) {
+            return Err(ManifoldError::InvalidCharacter {
+                symbol: *symbol,
+                row: y,
+                column: x,
+            });
         }
 
-        (
-            num_splits,
-            self.grid.last().unwrap().iter().fold(0, |acc, space| {
-                if let GridSpace::TachyonBeam(timelines) = space {
-                    acc + timelines
-                } else {
-                    acc
-                }
-            }),
-        )
+        Ok(TachyonManifold {
+            grid: grid.map(|space| space.unwrap()),
+        })
     }
 
-    fn process_row(current_row: &[GridSpace], next_row: &mut [GridSpace]) -> u64 {
-        let mut splits = 0;
-        for (index, space) in current_row.iter().enumerate() {
-            if let GridSpace::TachyonBeam(timelines) = space {
-                // Check the space below
-                match &mut next_row[index] {
-                    GridSpace::Empty => {
-                        // Move the beam down
-                        next_row[index] = GridSpace::TachyonBeam(*timelines);
-                    }
-                    GridSpace::Splitter => {
-                        // Add the timelines to the left
-                        if let GridSpace::TachyonBeam(existing_timelines) = &mut next_row[index - 1]
-                        {
-                            *existing_timelines += timelines;
-                        } else {
-                            next_row[index - 1] = GridSpace::TachyonBeam(*timelines);
-                        }
+    // Every beam is a state (x, y, heading) meaning it's entering that cell,
+    // and each cell sends it on to up to two more states. Timelines are the
+    // number of paths from a source to the bottom edge, counted in
+    // topological order over the states that are both reachable from a
+    // source and able to reach the bottom. A cycle among those means
+    // infinitely many paths; cycles elsewhere just trap or lose beams.
+    pub fn run_beam(&self) -> Result<BeamReport, ManifoldError> {
+        let num_states = self.grid.width() * self.grid.height() * 4;
+        let sources: Vec<usize> = self
+            .grid
+            .iter()
+            .filter(|(_, _, space)| **space == GridSpace::Source)
+            .map(|(x, y, _)| self.state_index((x, y, Direction::Down)))
+            .collect();
 
-                        // Add to the right
-                        if let GridSpace::TachyonBeam(existing_timelines) = &mut next_row[index + 1]
-                        {
-                            *existing_timelines += timelines;
-                        } else {
-                            next_row[index + 1] = GridSpace::TachyonBeam(*timelines);
-                        }
+        // Forward from the sources
+        let mut successors = vec![Vec::new(); num_states];
+        let mut exits = vec![false; num_states];
+        let mut reachable = vec![false; num_states];
+        let mut splitters_hit = Grid::new(self.grid.width(), self.grid.height(), false);
+        let mut queue: VecDeque<usize> = sources.iter().copied().collect();
+        for &source in &sources {
+            reachable[source] = true;
+        }
+        while let Some(state) = queue.pop_front() {
+            let beam = self.state_beam(state);
+            let (x, y, direction) = beam;
+            if self.grid[(x, y)] == GridSpace::Splitter && direction.is_vertical() {
+                splitters_hit[(x, y)] = true;
+            }
 
-                        splits += 1;
-                    }
-                    GridSpace::TachyonBeam(existing_timelines) => {
-                        // Just add the timelines to the existing beam
-                        *existing_timelines += timelines;
+            for outcome in self.propagate(beam) {
+                match outcome {
+                    Outcome::Enter(next) => {
+                        let next = self.state_index(next);
+                        successors[state].push(next);
+                        if !reachable[next] {
+                            reachable[next] = true;
+                            queue.push_back(next);
+                        }
                     }
+                    Outcome::Exit(Direction::Down) => exits[state] = true,
+                    Outcome::Exit(_) => {}
+                }
+            }
+        }
+
+        // Backward from the bottom edge
+        let mut predecessors = vec![Vec::new(); num_states];
+        for (state, nexts) in successors.iter().enumerate() {
+            for &next in nexts {
+                predecessors[next].push(state);
+            }
+        }
+        let mut useful = exits.clone();
+        let mut queue: VecDeque<usize> = (0..num_states).filter(|&state| exits[state]).collect();
+        while let Some(state) = queue.pop_front() {
+            for &previous in &predecessors[state] {
+                if !useful[previous] {
+                    useful[previous] = true;
+                    queue.push_back(previous);
+                }
+            }
+        }
+        for (useful, reachable) in useful.iter_mut().zip(&reachable) {
+            *useful &= reachable;
+        }
+
+        // Kahn's algorithm over the useful states, counting paths as it goes
+        let mut in_degree = vec![0; num_states];
+        for state in (0..num_states).filter(|&state| useful[state]) {
+            for &next in successors[state].iter().filter(|&&next| useful[next]) {
+                in_degree[next] += 1;
+            }
+        }
+        let mut paths = vec![0u64; num_states];
+        for &source in &sources {
+            paths[source] += 1;
+        }
+        let mut queue: VecDeque<usize> = (0..num_states)
+            .filter(|&state| useful[state] && in_degree[state] == 0)
+            .collect();
+        let mut timelines = 0;
+        let mut processed = 0;
+        while let Some(state) = queue.pop_front() {
+            processed += 1;
+            if exits[state] {
+                timelines += paths[state];
+            }
+            for &next in successors[state].iter().filter(|&&next| useful[next]) {
+                paths[next] += paths[state];
+                in_degree[next] -= 1;
+                if in_degree[next] == 0 {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if processed < useful.iter().filter(|&&useful| useful).count() {
+            // Every state left over has a left over predecessor, so walking
+            // back through them has to come round to a state twice
+            let stuck = |state: usize| useful[state] && in_degree[state] > 0;
+            let mut state = (0..num_states).find(|&state| stuck(state)).unwrap();
+            let mut seen = vec![false; num_states];
+            while !seen[state] {
+                seen[state] = true;
+                state = *predecessors[state]
+                    .iter()
+                    .find(|&&previous| stuck(previous))
+                    .unwrap();
+            }
+            let (x, y, _) = self.state_beam(state);
+            return Err(ManifoldError::Loop { x, y });
+        }
+
+        Ok(BeamReport {
+            splits: splitters_hit.iter().filter(|(_, _, hit)| **hit).count() as u64,
+            timelines,
+        })
+    }
+
+    // Where a beam entering (x, y) heading in `direction` goes next
+    fn propagate(&self, (x, y, direction): Beam) -> Vec<Outcome> {
+        let onward = |direction: Direction| {
+            let (dx, dy) = direction.offset();
+            match self.grid.offset(x, y, dx, dy) {
+                Some((x, y)) => Outcome::Enter((x, y, direction)),
+                None => Outcome::Exit(direction),
+            }
+        };
+
+        match self.grid[(x, y)] {
+            GridSpace::Empty | GridSpace::Source => vec![onward(direction)],
+            GridSpace::Splitter if direction.is_vertical() => {
+                // The two halves carry on from either side, beams split off the edge are lost
+                [Direction::Left, Direction::Right]
+                    .into_iter()
+                    .map(|side| {
+                        let (dx, _) = side.offset();
+                        match self.grid.offset(x, y, dx, 0) {
+                            Some((x, y)) => Outcome::Enter((x, y, direction)),
+                            None => Outcome::Exit(side),
+                        }
+                    })
+                    .collect()
+            }
+            GridSpace::Splitter => vec![onward(direction)],
+            GridSpace::Mirror(slant) => vec![onward(slant.reflect(direction))],
+            GridSpace::Absorber => Vec::new(),
+            GridSpace::Deflector(towards) => {
+                if direction.is_vertical() || direction == towards {
+                    vec![onward(towards)]
+                } else {
+                    Vec::new() // Going against a one-way deflector
                 }
             }
+            GridSpace::Merger => vec![onward(Direction::Down)],
+        }
+    }
+
+    fn state_index(&self, (x, y, direction): Beam) -> usize {
+        (y * self.grid.width() + x) * 4 + direction as usize
+    }
+
+    fn state_beam(&self, index: usize) -> Beam {
+        let cell = index / 4;
+        (
+            cell % self.grid.width(),
+            cell / self.grid.width(),
+            Direction::ALL[index % 4],
+        )
+    }
+}
+
+// A beam entering cell (x, y), heading in the given direction
+type Beam = (usize, usize, Direction);
+
+#[derive(Clone, Copy, Debug)]
+enum Outcome {
+    Enter(Beam),
+    Exit(Direction), // Leaves the manifold across that edge
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    fn is_vertical(self) -> bool {
+        matches!(self, Direction::Up | Direction::Down)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slant {
+    Forward,  // '/'
+    Backward, // '\'
+}
+
+impl Slant {
+    fn reflect(self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (Slant::Forward, Direction::Down) => Direction::Left,
+            (Slant::Forward, Direction::Up) => Direction::Right,
+            (Slant::Forward, Direction::Left) => Direction::Down,
+            (Slant::Forward, Direction::Right) => Direction::Up,
+            (Slant::Backward, Direction::Down) => Direction::Right,
+            (Slant::Backward, Direction::Up) => Direction::Left,
+            (Slant::Backward, Direction::Left) => Direction::Up,
+            (Slant::Backward, Direction::Right) => Direction::Down,
         }
-        splits
     }
 }
-#[derive(Clone, Debug)]
-enum GridSpace {
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridSpace {
     Empty,
-    TachyonBeam(u64), // Number of timelines in this space
-    Splitter,
+    Source,   // Sends one timeline down
+    Splitter, // Splits vertical beams to either side, horizontal ones pass through
+    Mirror(Slant),
+    Absorber,
+    Deflector(Direction), // Turns vertical beams Left or Right, blocks beams going the other way
+    Merger,               // Sends every beam down, whichever way it came in
+}
+
+impl GridSpace {
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        Some(match symbol {
+            '.' => GridSpace::Empty,
+            'S' => GridSpace::Source,
+            '^' => GridSpace::Splitter,
+            '/' => GridSpace::Mirror(Slant::Forward),
+            '\\' => GridSpace::Mirror(Slant::Backward),
+            '#' => GridSpace::Absorber,
+            '<' => GridSpace::Deflector(Direction::Left),
+            '>' => GridSpace::Deflector(Direction::Right),
+            'v' => GridSpace::Merger,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = ".......S.......\n...............\n.......^.......\n...............\n\
+                           ......^.^......\n...............\n.....^.^.^.....\n...............\n\
+                           ....^.^...^....\n...............\n...^.^...^.^...\n...............\n\
+                           ..^...^.....^..\n...............\n.^.^.^.^.^...^.\n...............\n";
+
+    fn run(rows: &[&str]) -> Result<BeamReport, ManifoldError> {
+        TachyonManifold::new(&rows.join("\n"))?.run_beam()
+    }
+
+    fn report(splits: u64, timelines: u64) -> Result<BeamReport, ManifoldError> {
+        Ok(BeamReport { splits, timelines })
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(
+            TachyonManifold::new(EXAMPLE).unwrap().run_beam(),
+            report(21, 40)
+        );
+    }
+
+    #[test]
+    fn splitters() {
+        assert_eq!(run(&[".S.", ".^.", "..."]), report(1, 2));
+        // Each half splits again, so the middle column carries two timelines
+        assert_eq!(run(&["..S..", "..^..", ".^.^.", "....."]), report(3, 4));
+        // A splitter only hit by beams going sideways doesn't split
+        assert_eq!(run(&["S...", "\\^.v", "...."]), report(0, 1));
+    }
+
+    #[test]
+    fn beams_split_off_an_edge_are_lost() {
+        assert_eq!(run(&["S.", "^.", ".."]), report(1, 1));
+        assert_eq!(run(&["S", "^", "."]), report(1, 0));
+    }
+
+    #[test]
+    fn mirrors() {
+        // '/' turns a falling beam left and a leftward one down
+        assert_eq!(run(&["..S", "/./", "..."]), report(0, 1));
+        // '\\' turns a falling beam right, and a rightward one down
+        assert_eq!(run(&["S..", "\\.\\", "..."]), report(0, 1));
+        // Sent off the left edge
+        assert_eq!(run(&[".S", "./", ".."]), report(0, 0));
+    }
+
+    #[test]
+    fn absorbers() {
+        assert_eq!(run(&["S.", "#.", ".."]), report(0, 0));
+        assert_eq!(run(&["..S..", "..^..", ".#...", "....."]), report(1, 1));
+    }
+
+    #[test]
+    fn deflectors() {
+        // A falling beam is turned, off the right edge unless something catches it
+        assert_eq!(run(&["S..", ">..", "..."]), report(0, 0));
+        assert_eq!(run(&["S..", ">.v", "..."]), report(0, 1));
+        // A beam already going the deflector's way passes through
+        assert_eq!(run(&["..S", "v</", "..."]), report(0, 1));
+        // Going against it is blocked
+        assert_eq!(run(&["..S", "v>/", "..."]), report(0, 0));
+    }
+
+    #[test]
+    fn mergers() {
+        // Every beam into a merger leaves downwards
+        assert_eq!(run(&["S..", "\\.v", "..."]), report(0, 1));
+        // The merged timelines still count separately
+        assert_eq!(run(&[".S.", ".^.", "v.v", "..."]), report(1, 2));
+        // Both halves turned into the same merger
+        assert_eq!(run(&["..S..", "..^..", ".\\v/.", "....."]), report(1, 2));
+    }
+
+    #[test]
+    fn loop_reaching_the_bottom() {
+        // Round the mirrors, with the splitter feeding the loop and the way out
+        let looping = ["..S...", "/..\\..", "...^..", "\\./...", "......"];
+        let on_loop = [
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (3, 2),
+            (2, 2),
+            (2, 3),
+            (1, 3),
+            (0, 3),
+            (0, 2),
+        ];
+        match run(&looping) {
+            Err(ManifoldError::Loop { x, y }) => assert!(on_loop.contains(&(x, y)), "({x}, {y})"),
+            other => panic!("Expected a loop, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn loop_that_never_gets_out() {
+        // Same loop with its way out blocked, the beam just goes round
+        let trapped = ["..S...", "/..\\..", "...^..", "\\./.#.", "......"];
+        assert_eq!(run(&trapped), report(1, 0));
+    }
+
+    #[test]
+    fn ragged_rows() {
+        assert_eq!(
+            TachyonManifold::new("..S..\n.....\n..^.\n.....\n").err(),
+            Some(ManifoldError::RaggedRow {
+                row: 2,
+                length: 4,
+                expected: 5
+            })
+        );
+        assert_eq!(
+            TachyonManifold::new("..S\n...\n....").err(),
+            Some(ManifoldError::RaggedRow {
+                row: 2,
+                length: 4,
+                expected: 3
+            })
+        );
+        assert!(TachyonManifold::new("..S..\r\n.....\r\n").is_ok());
+    }
 }
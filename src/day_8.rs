@@ -1,4 +1,6 @@
-use std::{collections::HashMap, hash::Hash};
+use std::hash::Hash;
+
+use crate::fx_hash::FxHashMap;
 
 pub struct Day8;
 
@@ -15,7 +17,7 @@ impl crate::Day for Day8 {
             .map(|coord| JunctionBox::new(coord[0], coord[1], coord[2]))
             .collect::<Vec<JunctionBox>>();
 
-        let mut circuits = HashMap::<usize, usize>::new();
+        let mut circuits = FxHashMap::<usize, usize>::default();
         let mut circuit_id = 0;
         let mut part1 = 0;
         let mut last_pair = (JunctionBox::new(0, 0, 0), JunctionBox::new(0, 0, 0));
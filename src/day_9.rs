@@ -1,4 +1,6 @@
-use std::{collections::HashSet, hash::Hash};
+use std::hash::Hash;
+
+use crate::fx_hash::FxHashSet;
 
 pub struct Day9;
 
@@ -92,13 +94,13 @@ impl Point {
 struct Polygon {
     vertices: Vec<Point>,
     segments: Vec<(Point, Point)>,
-    polygon_event_points: HashSet<Point>,
+    polygon_event_points: FxHashSet<Point>,
 }
 
 impl Polygon {
     pub fn new(vertices: Vec<Point>) -> Self {
         let mut segments = Vec::new();
-        let mut polygon_event_points = HashSet::new();
+        let mut polygon_event_points = FxHashSet::default();
         for i in 0..vertices.len() - 1 {
             segments.push((vertices[i].clone(), vertices[i + 1].clone()));
 
@@ -161,8 +163,8 @@ impl Polygon {
         &self,
         top_left: &Point,
         bottom_right: &Point,
-    ) -> HashSet<Point> {
-        let mut intersection_points = HashSet::new();
+    ) -> FxHashSet<Point> {
+        let mut intersection_points = FxHashSet::default();
 
         let left_x = top_left.x;
         let right_x = bottom_right.x;
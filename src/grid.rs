@@ -0,0 +1,349 @@
+/*
+    Rectangular 2D grid stored as one flat row-major Vec.
+    Coordinates are (x, y) with x the column and y the row,
+    (0, 0) being the top left
+*/
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+/// Offsets to the 4 orthogonal neighbours
+pub const ORTHOGONAL: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+/// Offsets to all 8 surrounding neighbours
+pub const SURROUNDING: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// A row whose length differs from the first row's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaggedRow {
+    pub row: usize,
+    pub length: usize,
+    pub expected: usize,
+}
+
+impl fmt::Display for RaggedRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Grid row {} has length {}, expected {}",
+            self.row, self.length, self.expected
+        )
+    }
+}
+
+impl std::error::Error for RaggedRow {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self
+    where
+        T: Clone,
+    {
+        Grid {
+            width,
+            height,
+            cells: vec![fill; width * height],
+        }
+    }
+
+    /// Panics if the rows aren't all the same length
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Self {
+        Self::try_from_rows(rows).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_from_rows(rows: Vec<Vec<T>>) -> Result<Self, RaggedRow> {
+        let height = rows.len();
+        let width = rows.first().map_or(0, Vec::len);
+
+        let mut cells = Vec::with_capacity(width * height);
+        for (y, row) in rows.into_iter().enumerate() {
+            if row.len() != width {
+                return Err(RaggedRow {
+                    row: y,
+                    length: row.len(),
+                    expected: width,
+                });
+            }
+            cells.extend(row);
+        }
+
+        Ok(Grid {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    /// One row per line, mapping each character to a cell.
+    /// Panics if the lines aren't all the same length
+    pub fn parse(input: &str, parse_cell: impl FnMut(char) -> T) -> Self {
+        Self::try_parse(input, parse_cell).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_parse(
+        input: &str,
+        mut parse_cell: impl FnMut(char) -> T,
+    ) -> Result<Self, RaggedRow> {
+        Self::try_from_rows(
+            input
+                .lines()
+                .map(|line| line.chars().map(&mut parse_cell).collect())
+                .collect(),
+        )
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        self.contains(x, y).then(|| &self.cells[y * self.width + x])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        if self.contains(x, y) {
+            Some(&mut self.cells[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// Position one (dx, dy) step away, if it's still on the grid
+    pub fn offset(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+        let new_x = x.checked_add_signed(dx)?;
+        let new_y = y.checked_add_signed(dy)?;
+        self.contains(new_x, new_y).then_some((new_x, new_y))
+    }
+
+    /// In-bounds cells at the given offsets from (x, y), as (x, y, cell)
+    pub fn neighbours<'a>(
+        &'a self,
+        x: usize,
+        y: usize,
+        offsets: &'a [(isize, isize)],
+    ) -> impl Iterator<Item = (usize, usize, &'a T)> + 'a {
+        offsets.iter().filter_map(move |&(dx, dy)| {
+            let (new_x, new_y) = self.offset(x, y, dx, dy)?;
+            Some((new_x, new_y, &self[(new_x, new_y)]))
+        })
+    }
+
+    pub fn neighbours4(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize, &T)> {
+        self.neighbours(x, y, &ORTHOGONAL)
+    }
+
+    pub fn neighbours8(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize, &T)> {
+        self.neighbours(x, y, &SURROUNDING)
+    }
+
+    /// Every position, row by row
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> + use<T> {
+        let width = self.width;
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    /// Every cell with its position, row by row
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &T)> {
+        self.positions()
+            .zip(self.cells.iter())
+            .map(|((x, y), cell)| (x, y, cell))
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[T]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    pub fn rows_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut [T]> {
+        // A zero width grid has no cells to chunk, but still has its rows
+        let empty_rows = if self.width == 0 { self.height } else { 0 };
+        self.cells
+            .chunks_exact_mut(self.width.max(1))
+            .chain((0..empty_rows).map(|_| Default::default()))
+    }
+
+    pub fn column(&self, x: usize) -> impl Iterator<Item = &T> {
+        assert!(x < self.width, "Column {x} out of bounds");
+        self.cells.iter().skip(x).step_by(self.width)
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(|x| self.column(x))
+    }
+
+    pub fn transpose(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        Grid {
+            width: self.height,
+            height: self.width,
+            cells: self.columns().flatten().cloned().collect(),
+        }
+    }
+
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        self.get(x, y).unwrap_or_else(|| {
+            panic!(
+                "Position ({x}, {y}) out of bounds for {}x{} grid",
+                self.width, self.height
+            )
+        })
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        let (width, height) = (self.width, self.height);
+        self.get_mut(x, y).unwrap_or_else(|| {
+            panic!("Position ({x}, {y}) out of bounds for {width}x{height} grid")
+        })
+    }
+}
+
+impl<T> fmt::Display for Grid<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (y, row) in self.rows().enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for cell in row {
+                write!(f, "{cell}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digits() -> Grid<u8> {
+        Grid::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]])
+    }
+
+    #[test]
+    fn transpose_and_columns() {
+        let grid = digits();
+        assert_eq!(grid.column(1).copied().collect::<Vec<_>>(), [2, 5]);
+        let columns: Vec<Vec<u8>> = grid
+            .columns()
+            .map(|column| column.copied().collect())
+            .collect();
+        assert_eq!(columns, [[1, 4], [2, 5], [3, 6]]);
+
+        let transposed = grid.transpose();
+        assert_eq!((transposed.width(), transposed.height()), (2, 3));
+        assert_eq!(
+            transposed.rows().collect::<Vec<_>>(),
+            [[1, 4], [2, 5], [3, 6]]
+        );
+        assert_eq!(transposed.transpose(), grid);
+    }
+
+    #[test]
+    fn neighbours_at_corners() {
+        let grid = digits();
+        let values = |neighbours: Vec<(usize, usize, &u8)>| -> Vec<u8> {
+            neighbours.into_iter().map(|(_, _, &cell)| cell).collect()
+        };
+        assert_eq!(values(grid.neighbours4(0, 0).collect()), [2, 4]);
+        assert_eq!(values(grid.neighbours8(0, 0).collect()), [2, 4, 5]);
+        assert_eq!(values(grid.neighbours4(2, 1).collect()), [3, 5]);
+        assert_eq!(values(grid.neighbours8(2, 1).collect()), [2, 3, 5]);
+        assert_eq!(values(grid.neighbours8(1, 0).collect()), [1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn offset_off_the_grid() {
+        let grid = digits();
+        assert_eq!(grid.offset(0, 0, -1, 0), None);
+        assert_eq!(grid.offset(0, 0, 0, -1), None);
+        assert_eq!(grid.offset(2, 1, 1, 0), None);
+        assert_eq!(grid.offset(2, 1, 0, 1), None);
+        assert_eq!(grid.offset(usize::MAX, 0, 1, 0), None);
+        assert_eq!(grid.offset(1, 1, 1, -1), Some((2, 0)));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(digits().to_string(), "123\n456");
+        let parsed = Grid::parse("#.\n.#\n", |c| c);
+        assert_eq!(parsed.to_string(), "#.\n.#");
+    }
+
+    #[test]
+    fn zero_width_grid() {
+        let mut grid: Grid<u8> = Grid::new(0, 3, 0);
+        assert_eq!(grid.rows().count(), 3);
+        assert!(grid.rows().all(<[u8]>::is_empty));
+        assert_eq!(grid.rows_mut().count(), 3);
+        assert_eq!(grid.columns().count(), 0);
+        assert_eq!(grid.iter().count(), 0);
+        assert_eq!(grid.to_string(), "\n\n");
+        assert_eq!(grid.transpose().height(), 0);
+    }
+
+    #[test]
+    fn ragged_rows() {
+        assert_eq!(
+            Grid::try_parse("abc\nde\nfgh", |c| c),
+            Err(RaggedRow {
+                row: 1,
+                length: 2,
+                expected: 3
+            })
+        );
+        assert_eq!(
+            Grid::try_from_rows(vec![vec![1], vec![2, 3]]),
+            Err(RaggedRow {
+                row: 1,
+                length: 2,
+                expected: 1
+            })
+        );
+        assert!(Grid::try_from_rows(Vec::<Vec<u8>>::new()).is_ok());
+    }
+}
@@ -0,0 +1,295 @@
+/*
+    Set of integers stored as sorted, disjoint inclusive intervals.
+    Touching or overlapping intervals are always merged, so every
+    operation works on the k intervals instead of the values in them
+*/
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Integer types an IntervalSet can hold
+pub trait IntervalBound: Copy + Ord + fmt::Debug {
+    const MIN: Self;
+    const MAX: Self;
+
+    fn successor(self) -> Option<Self>;
+    fn predecessor(self) -> Option<Self>;
+    /// Number of values in start..=end, which can be one more than the type
+    /// holds. None if that doesn't fit in a u128 (all of i128 or u128)
+    fn count(start: Self, end: Self) -> Option<u128>;
+}
+
+macro_rules! impl_interval_bound {
+    ($($t:ty),*) => {
+        $(
+            impl IntervalBound for $t {
+                const MIN: Self = <$t>::MIN;
+                const MAX: Self = <$t>::MAX;
+
+                fn successor(self) -> Option<Self> {
+                    self.checked_add(1)
+                }
+
+                fn predecessor(self) -> Option<Self> {
+                    self.checked_sub(1)
+                }
+
+                fn count(start: Self, end: Self) -> Option<u128> {
+                    (end.abs_diff(start) as u128).checked_add(1)
+                }
+            }
+        )*
+    };
+}
+
+impl_interval_bound!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize
+);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct IntervalSet<T> {
+    intervals: Vec<(T, T)>, // Inclusive, sorted, with gaps between consecutive intervals
+}
+
+impl<T> Default for IntervalSet<T>
+where
+    T: IntervalBound,
+{
+    fn default() -> Self {
+        IntervalSet {
+            intervals: Vec::new(),
+        }
+    }
+}
+
+impl<T> IntervalSet<T>
+where
+    T: IntervalBound,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sorts and merges the ranges, empty ranges are ignored
+    pub fn from_ranges(ranges: impl IntoIterator<Item = RangeInclusive<T>>) -> Self {
+        let mut intervals: Vec<(T, T)> = ranges
+            .into_iter()
+            .map(|range| range.into_inner())
+            .filter(|(start, end)| start <= end)
+            .collect();
+        intervals.sort_unstable();
+
+        let mut merged: Vec<(T, T)> = Vec::with_capacity(intervals.len());
+        for (start, end) in intervals {
+            match merged.last_mut() {
+                // Overlapping or touching the previous interval
+                Some((_, last_end)) if last_end.successor().is_none_or(|next| start <= next) => {
+                    *last_end = T::max(*last_end, end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+
+        IntervalSet { intervals: merged }
+    }
+
+    pub fn insert(&mut self, range: RangeInclusive<T>) {
+        let intervals = std::mem::take(&mut self.intervals);
+        *self = Self::from_ranges(
+            intervals
+                .into_iter()
+                .map(|(start, end)| start..=end)
+                .chain([range]),
+        );
+    }
+
+    /// Binary search, O(log k)
+    pub fn contains(&self, value: T) -> bool {
+        let index = self.intervals.partition_point(|&(start, _)| start <= value);
+        index > 0 && value <= self.intervals[index - 1].1
+    }
+
+    /// Number of values in the set, or None if there are more than a u128
+    /// holds (only possible for 128-bit types). O(k)
+    pub fn covered_len(&self) -> Option<u128> {
+        self.intervals.iter().try_fold(0u128, |len, &(start, end)| {
+            len.checked_add(T::count(start, end)?)
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Number of disjoint intervals
+    pub fn num_intervals(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = RangeInclusive<T>> {
+        self.intervals.iter().map(|&(start, end)| start..=end)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_ranges(self.ranges().chain(other.ranges()))
+    }
+
+    /// Two-pointer sweep over both sets, O(k1 + k2)
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (start_a, end_a) = self.intervals[i];
+            let (start_b, end_b) = other.intervals[j];
+
+            let start = T::max(start_a, start_b);
+            let end = T::min(end_a, end_b);
+            if start <= end {
+                intervals.push((start, end));
+            }
+
+            // Whichever ends first can't overlap anything further on
+            if end_a < end_b {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.intersection(&other.complement())
+    }
+
+    /// Everything from T::MIN to T::MAX not in the set
+    pub fn complement(&self) -> Self {
+        let mut intervals = Vec::with_capacity(self.intervals.len() + 1);
+        let mut next_start = Some(T::MIN);
+        for &(start, end) in &self.intervals {
+            if let (Some(gap_start), Some(gap_end)) = (next_start, start.predecessor())
+                && gap_start <= gap_end
+            {
+                intervals.push((gap_start, gap_end));
+            }
+            next_start = end.successor();
+        }
+        if let Some(gap_start) = next_start {
+            intervals.push((gap_start, T::MAX));
+        }
+
+        IntervalSet { intervals }
+    }
+}
+
+impl<T> FromIterator<RangeInclusive<T>> for IntervalSet<T>
+where
+    T: IntervalBound,
+{
+    fn from_iter<I: IntoIterator<Item = RangeInclusive<T>>>(iter: I) -> Self {
+        Self::from_ranges(iter)
+    }
+}
+
+impl<T> fmt::Debug for IntervalSet<T>
+where
+    T: IntervalBound,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.ranges()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rng::XorShift;
+
+    fn set<T: IntervalBound>(ranges: &[RangeInclusive<T>]) -> IntervalSet<T> {
+        ranges.iter().cloned().collect()
+    }
+
+    #[test]
+    fn touching_intervals_merge() {
+        let merged = set(&[5..=9, 1..=4, 10..=10, 12..=13]);
+        assert_eq!(merged.ranges().collect::<Vec<_>>(), [1..=10, 12..=13]);
+
+        let mut inserted = set(&[1..=2, 6..=7]);
+        inserted.insert(3..=5);
+        assert_eq!(inserted.ranges().collect::<Vec<_>>(), [1..=7]);
+
+        // Empty ranges don't bridge anything
+        assert_eq!(
+            set(&[1..=2, RangeInclusive::new(5, 3), 4..=4]).num_intervals(),
+            2
+        );
+    }
+
+    #[test]
+    fn bounds_at_the_edges_of_the_type() {
+        assert_eq!(u8::MAX.successor(), None);
+        assert_eq!(u8::MIN.predecessor(), None);
+        assert_eq!(i64::MAX.successor(), None);
+        assert_eq!(i64::MIN.predecessor(), None);
+        assert_eq!(i128::MIN.predecessor(), None);
+
+        // Merging past T::MAX must not overflow
+        let top = set(&[u64::MAX - 1..=u64::MAX, u64::MAX..=u64::MAX]);
+        assert_eq!(top.ranges().collect::<Vec<_>>(), [u64::MAX - 1..=u64::MAX]);
+        assert!(top.contains(u64::MAX));
+
+        let edges = set(&[i8::MIN..=-100, 100..=i8::MAX]);
+        assert_eq!(edges.complement().ranges().collect::<Vec<_>>(), [-99..=99]);
+        assert_eq!(edges.complement().complement(), edges);
+    }
+
+    #[test]
+    fn covered_len_of_whole_domain() {
+        assert_eq!(set(&[u8::MIN..=u8::MAX]).covered_len(), Some(256));
+        assert_eq!(set(&[i64::MIN..=i64::MAX]).covered_len(), Some(1 << 64));
+        assert_eq!(set(&[u64::MIN..=u64::MAX]).covered_len(), Some(1 << 64));
+        assert_eq!(set(&[0..=u128::MAX - 1]).covered_len(), Some(u128::MAX));
+        assert_eq!(set(&[u128::MIN..=u128::MAX]).covered_len(), None);
+        assert_eq!(IntervalSet::<u8>::new().covered_len(), Some(0));
+    }
+
+    #[test]
+    fn complement_of_empty_set() {
+        let everything = IntervalSet::<i32>::new().complement();
+        assert_eq!(
+            everything.ranges().collect::<Vec<_>>(),
+            [i32::MIN..=i32::MAX]
+        );
+        assert!(everything.complement().is_empty());
+    }
+
+    #[test]
+    fn matches_membership_on_every_u8() {
+        let mut random = XorShift::new(0x2545f4914f6cdd1d);
+        let mut next = || random.next_u64() as u8;
+        let mut random_set = || {
+            let ranges: Vec<RangeInclusive<u8>> =
+                (0..next() % 6).map(|_| next()..=next()).collect();
+            let members: Vec<bool> = (0..=u8::MAX)
+                .map(|value| ranges.iter().any(|range| range.contains(&value)))
+                .collect();
+            (set(&ranges), members)
+        };
+
+        for _ in 0..200 {
+            let (a, in_a) = random_set();
+            let (b, in_b) = random_set();
+            for value in 0..=u8::MAX {
+                let (in_a, in_b) = (in_a[value as usize], in_b[value as usize]);
+                assert_eq!(a.contains(value), in_a);
+                assert_eq!(a.union(&b).contains(value), in_a || in_b);
+                assert_eq!(a.intersection(&b).contains(value), in_a && in_b);
+                assert_eq!(a.difference(&b).contains(value), in_a && !in_b);
+                assert_eq!(a.complement().contains(value), !in_a);
+            }
+            let count = in_a.iter().filter(|&&member| member).count();
+            assert_eq!(a.covered_len(), Some(count as u128));
+        }
+    }
+}
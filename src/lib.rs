@@ -0,0 +1,26 @@
+pub mod day_1;
+pub mod day_10;
+pub mod day_11;
+pub mod day_2;
+pub mod day_3;
+pub mod day_4;
+pub mod day_5;
+pub mod day_6;
+pub mod day_7;
+pub mod day_8;
+pub mod day_9;
+pub mod fx_hash;
+pub mod grid;
+pub mod interval_set;
+pub mod memoizer;
+#[cfg(test)]
+mod test_rng;
+
+pub trait Day {
+    fn run(input: String) -> DayResult;
+}
+
+pub struct DayResult {
+    pub part_1: u64,
+    pub part_2: u64,
+}
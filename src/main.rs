@@ -1,36 +1,16 @@
-mod day_1;
-mod day_10;
-mod day_11;
-mod day_2;
-mod day_3;
-mod day_4;
-mod day_5;
-mod day_6;
-mod day_7;
-mod day_8;
-mod day_9;
-mod memoizer;
-
-use day_1::Day1;
-use day_2::Day2;
-use day_3::Day3;
-use day_4::Day4;
-use day_5::Day5;
-use day_6::Day6;
-use day_7::Day7;
-use day_8::Day8;
-use day_9::Day9;
-use day_10::Day10;
-use day_11::Day11;
-
-pub trait Day {
-    fn run(input: String) -> DayResult;
-}
-
-pub struct DayResult {
-    pub part_1: u64,
-    pub part_2: u64,
-}
+use advent_of_code_2025::day_1::Day1;
+use advent_of_code_2025::day_2::Day2;
+use advent_of_code_2025::day_3::{self, Day3};
+use advent_of_code_2025::day_4::Day4;
+use advent_of_code_2025::day_5::Day5;
+use advent_of_code_2025::day_6::Day6;
+use advent_of_code_2025::day_7::Day7;
+use advent_of_code_2025::day_8::Day8;
+use advent_of_code_2025::day_9::Day9;
+use advent_of_code_2025::day_10::Day10;
+use advent_of_code_2025::day_11::Day11;
+use advent_of_code_2025::memoizer::{hash_input, memo_dir, remove_stale_memos};
+use advent_of_code_2025::{Day, DayResult};
 
 struct DayInfo {
     day: u8,
@@ -43,7 +23,63 @@ impl DayInfo {
     }
 }
 
+const USAGE: &str = "\
+usage:
+    advent_of_code_2025                      run every day
+    advent_of_code_2025 joltage <digits>     day 3 total joltage picking <digits> batteries per bank
+    advent_of_code_2025 joltage-table        day 3 total joltage for every number of batteries";
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => run_all_days(),
+        ["joltage", num_digits] => {
+            let Ok(num_digits) = num_digits.parse() else {
+                exit_with_usage();
+            };
+            let banks = day_3::parse_banks(&read_input(3));
+            // Every bank has to have enough batteries to pick from
+            let shortest = banks.iter().map(Vec::len).min().unwrap_or(0);
+            if num_digits > shortest {
+                eprintln!(
+                    "Can't pick {num_digits} batteries per bank, the shortest bank has {shortest}"
+                );
+                std::process::exit(1);
+            }
+            println!("{}", day_3::total_joltage(&banks, num_digits));
+        }
+        ["joltage-table"] => {
+            let banks = day_3::parse_banks(&read_input(3));
+            let table = day_3::joltage_table(&banks);
+            let header = "total joltage";
+            let width = table
+                .last()
+                .map_or(0, |(_, total)| total.to_string().len())
+                .max(header.len());
+            println!("{:>6}  {header:>width$}", "digits");
+            for (num_digits, total) in table {
+                println!("{num_digits:>6}  {total:>width$}");
+            }
+        }
+        _ => exit_with_usage(),
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(1);
+}
+
+fn read_input(day: u8) -> String {
+    std::fs::read_to_string(format!("inputs/day_{day}.txt")).expect("Failed to read input file")
+}
+
+fn run_all_days() {
     println!("Advent of Code 2025");
 
     let days: &[DayInfo] = &[
@@ -61,8 +97,9 @@ fn main() {
     ];
 
     for day_info in days {
-        let input = std::fs::read_to_string(format!("inputs/day_{}.txt", day_info.day))
-            .expect("Failed to read input file");
+        let input = read_input(day_info.day);
+        remove_stale_memos(&memo_dir(day_info.day), hash_input(&input))
+            .expect("Failed to invalidate stale memo tables");
         let result = (day_info.run_fn)(input);
         println!(
             "Day {}:\n\tPart 1: {}\n\tPart 2: {}",
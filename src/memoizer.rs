@@ -3,7 +3,17 @@
     for faster hashing
 */
 
+use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::fs;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::fx_hash::FxHasher;
+
+const MEMO_DIR: &str = "memo";
+const MEMO_EXTENSION: &str = "memo";
 
 /// HashRef
 /// Abstraction around &T that hashes like a *const T,
@@ -51,32 +61,55 @@ where
     }
 }
 
-pub struct Memoizer<U, T> {
-    table: HashMap<U, T>,
+/// Memoizer
+/// Lookup table from arguments to results, generic over the
+/// hasher so that callers with cheap keys (see fx_hash) can
+/// skip SipHash
+pub struct Memoizer<U, T, S = RandomState> {
+    table: HashMap<U, T, S>,
 }
 
-impl<U, T> Default for Memoizer<U, T>
+impl<U, T, S> Default for Memoizer<U, T, S>
 where
     U: std::hash::Hash + Eq + Clone,
     T: Clone,
+    S: BuildHasher + Default,
 {
     fn default() -> Self {
         Memoizer {
-            table: HashMap::new(),
+            table: HashMap::default(),
         }
     }
 }
 
-impl<U, T> Memoizer<U, T>
+impl<U, T, S> Memoizer<U, T, S>
 where
     U: std::hash::Hash + Eq + Clone,
     T: Clone,
+    S: BuildHasher + Default,
 {
     pub fn new() -> Self {
         Self::default()
     }
+}
 
-    pub fn get<'b>(&'b self, element: &U) -> Option<&'b T> {
+impl<U, T, S> Memoizer<U, T, S>
+where
+    U: std::hash::Hash + Eq + Clone,
+    T: Clone,
+    S: BuildHasher,
+{
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Memoizer {
+            table: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn get<'b, Q>(&'b self, element: &Q) -> Option<&'b T>
+    where
+        U: Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
         self.table.get(element)
     }
 
@@ -84,3 +117,560 @@ where
         self.table.insert(element, value);
     }
 }
+
+impl<U, T, S> Memoizer<U, T, S>
+where
+    U: std::hash::Hash + Eq + Clone + MemoSerialize,
+    T: Clone + MemoSerialize,
+    S: BuildHasher + Default,
+{
+    /// Writes the table as text: a header line holding the input hash,
+    /// then one `key\tvalue` line per entry. The table goes to a temporary
+    /// file first and is renamed into place, so an interrupted save never
+    /// leaves a truncated table behind
+    pub fn save(&self, path: &Path, input_hash: u64) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut writer = BufWriter::new(fs::File::create(&temp_path)?);
+        writeln!(writer, "{input_hash:016x}")?;
+        for (key, value) in &self.table {
+            writeln!(writer, "{}\t{}", key.serialize(), value.serialize())?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temp_path, path)
+    }
+
+    /// Reads a table written by `save`. Returns None if there is no
+    /// saved table or it was built from a different input
+    pub fn load(path: &Path, input_hash: u64) -> io::Result<Option<Self>> {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next().transpose()?;
+        if header.is_none_or(|header| header != format!("{input_hash:016x}")) {
+            return Ok(None);
+        }
+
+        let mut memoizer = Self::new();
+        for line in lines {
+            let line = line?;
+            let entry = line
+                .split_once('\t')
+                .and_then(|(key, value)| Some((U::deserialize(key)?, T::deserialize(value)?)));
+            let Some((key, value)) = entry else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Malformed memo entry {line:?} in {}", path.display()),
+                ));
+            };
+            memoizer.insert(key, value);
+        }
+
+        Ok(Some(memoizer))
+    }
+
+    /// Loads the saved table if there is a valid one, otherwise starts empty
+    pub fn load_or_new(path: &Path, input_hash: u64) -> io::Result<Self> {
+        Ok(Self::load(path, input_hash)?.unwrap_or_default())
+    }
+}
+
+/// MemoSerialize
+/// Text encoding for persisted memo keys and values.
+/// Encodings must not contain tabs, newlines or spaces,
+/// which separate entries, keys from values and tuple elements
+pub trait MemoSerialize: Sized {
+    fn serialize(&self) -> String;
+    fn deserialize(text: &str) -> Option<Self>;
+}
+
+macro_rules! impl_memo_serialize_from_str {
+    ($($t:ty),*) => {
+        $(
+            impl MemoSerialize for $t {
+                fn serialize(&self) -> String {
+                    self.to_string()
+                }
+
+                fn deserialize(text: &str) -> Option<Self> {
+                    text.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_memo_serialize_from_str!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool
+);
+
+// Backslash escapes for the separators, so any string round trips
+impl MemoSerialize for String {
+    fn serialize(&self) -> String {
+        let mut text = String::with_capacity(self.len());
+        for c in self.chars() {
+            match c {
+                '\\' => text.push_str("\\\\"),
+                '\t' => text.push_str("\\t"),
+                '\n' => text.push_str("\\n"),
+                '\r' => text.push_str("\\r"),
+                ' ' => text.push_str("\\s"),
+                c => text.push(c),
+            }
+        }
+        text
+    }
+
+    fn deserialize(text: &str) -> Option<Self> {
+        let mut string = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                string.push(c);
+                continue;
+            }
+            string.push(match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                's' => ' ',
+                _ => return None,
+            });
+        }
+        Some(string)
+    }
+}
+
+impl<A, B> MemoSerialize for (A, B)
+where
+    A: MemoSerialize,
+    B: MemoSerialize,
+{
+    fn serialize(&self) -> String {
+        format!("{} {}", self.0.serialize(), self.1.serialize())
+    }
+
+    fn deserialize(text: &str) -> Option<Self> {
+        let (a, b) = text.split_once(' ')?;
+        Some((A::deserialize(a)?, B::deserialize(b)?))
+    }
+}
+
+impl<A, B, C> MemoSerialize for (A, B, C)
+where
+    A: MemoSerialize,
+    B: MemoSerialize,
+    C: MemoSerialize,
+{
+    fn serialize(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.0.serialize(),
+            self.1.serialize(),
+            self.2.serialize()
+        )
+    }
+
+    fn deserialize(text: &str) -> Option<Self> {
+        let mut parts = text.split(' ');
+        let result = (
+            A::deserialize(parts.next()?)?,
+            B::deserialize(parts.next()?)?,
+            C::deserialize(parts.next()?)?,
+        );
+        parts.next().is_none().then_some(result)
+    }
+}
+
+/// Stable hash of a puzzle input, used to tell whether a saved
+/// memo table belongs to it. FxHash has no random seed, so this
+/// is the same from run to run
+pub fn hash_input(input: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(input.as_bytes());
+    hasher.write_usize(input.len());
+    hasher.finish()
+}
+
+/// Where a day's persisted memo tables live
+pub fn memo_path(day: u8, name: &str) -> PathBuf {
+    memo_dir(day).join(format!("{name}.{MEMO_EXTENSION}"))
+}
+
+pub fn memo_dir(day: u8) -> PathBuf {
+    Path::new(MEMO_DIR).join(format!("day_{day}"))
+}
+
+/// Deletes every memo table in `dir` that was built from a different
+/// input, so a changed input file never warm-starts from stale results
+pub fn remove_stale_memos(dir: &Path, input_hash: u64) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    let expected_header = format!("{input_hash:016x}");
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != MEMO_EXTENSION) {
+            continue;
+        }
+
+        let mut header = String::new();
+        BufReader::new(fs::File::open(&path)?).read_line(&mut header)?;
+        if header.trim_end() != expected_header {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// DenseMemo
+/// Memo table for DP state spaces that are already small integers,
+/// e.g. (offset, digits remaining). Stored row-major in one flat
+/// Vec so lookups are just index arithmetic, no hashing.
+/// Bounds are exclusive, one per dimension.
+/// Nothing is allocated until the first insert, so creating a
+/// table for a recursion that never needs it is free
+pub struct DenseMemo<T, const N: usize> {
+    bounds: [usize; N],
+    table: Vec<Option<T>>,
+}
+
+impl<T, const N: usize> DenseMemo<T, N>
+where
+    T: Clone,
+{
+    pub fn new(bounds: [usize; N]) -> Self {
+        DenseMemo {
+            bounds,
+            table: Vec::new(),
+        }
+    }
+
+    /// None if nothing is stored at `index`, including when it's out of bounds
+    pub fn get(&self, index: [usize; N]) -> Option<&T> {
+        let flat_index = self.flat_index(index)?;
+        self.table.get(flat_index)?.as_ref()
+    }
+
+    /// Panics if `index` is out of bounds
+    pub fn insert(&mut self, index: [usize; N], value: T) {
+        let flat_index = self
+            .flat_index(index)
+            .unwrap_or_else(|| panic!("DenseMemo index {index:?} out of bounds {:?}", self.bounds));
+        if self.table.is_empty() {
+            self.table = vec![None; self.bounds.iter().product()];
+        }
+        self.table[flat_index] = Some(value);
+    }
+
+    // None if any coordinate is past its bound
+    fn flat_index(&self, index: [usize; N]) -> Option<usize> {
+        index
+            .iter()
+            .zip(self.bounds.iter())
+            .try_fold(0, |acc, (&i, &bound)| {
+                (i < bound).then_some(acc * bound + i)
+            })
+    }
+}
+
+/// MemoTable
+/// The lookups memoized! needs, so the same macro can drive
+/// a hashed Memoizer or a DenseMemo
+pub trait MemoTable<K, V> {
+    fn lookup(&self, key: &K) -> Option<V>;
+    fn store(&mut self, key: K, value: V);
+}
+
+impl<U, T, S> MemoTable<U, T> for Memoizer<U, T, S>
+where
+    U: std::hash::Hash + Eq + Clone,
+    T: Clone,
+    S: BuildHasher,
+{
+    fn lookup(&self, key: &U) -> Option<T> {
+        self.get(key).cloned()
+    }
+
+    fn store(&mut self, key: U, value: T) {
+        self.insert(key, value);
+    }
+}
+
+impl<T, const N: usize> MemoTable<[usize; N], T> for DenseMemo<T, N>
+where
+    T: Clone,
+{
+    fn lookup(&self, key: &[usize; N]) -> Option<T> {
+        self.get(*key).cloned()
+    }
+
+    fn store(&mut self, key: [usize; N], value: T) {
+        self.insert(key, value);
+    }
+}
+
+/// memoized!
+/// Turns a plain recursive function into a memoized one.
+/// A fresh table is created for every outside call and shared
+/// by the recursive calls, so recursion inside the body is
+/// written exactly as it would be without memoization, including
+/// calls nested in each other's arguments like `ack(m - 1, ack(m, n - 1))`.
+///
+/// By default the key is the tuple of all arguments, which must be
+/// owned and Hash + Eq + Clone. Otherwise give the key explicitly:
+///
+/// ```text
+/// memoized! {
+///     fn count(items: &[u8], budget: u8) -> u64
+///     where key: (usize, u8) = (items.len(), budget)
+///     { ... }
+/// }
+/// ```
+///
+/// When the key is a few small integers, a DenseMemo skips hashing.
+/// The bounds are worked out from the arguments of the outside call:
+///
+/// ```text
+/// memoized! {
+///     fn count(items: &[u8], budget: u8) -> u64
+///     where dense key: [usize; 2] = [items.len(), budget as usize],
+///         bounds = [items.len() + 1, budget as usize + 1]
+///     { ... }
+/// }
+/// ```
+#[macro_export]
+macro_rules! memoized {
+    (
+        @impl
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;
+        table: $table_ty:ty = $table:expr;
+        key: $key_ty:ty = $key:tt;
+        $body:block
+    ) => {
+        $(#[$meta])*
+        $vis fn $name($($arg: $ty),*) -> $ret {
+            fn memoized(memo_table: &::std::cell::RefCell<$table_ty>, $($arg: $ty),*) -> $ret {
+                let memo_key: $key_ty = $key;
+
+                let cached =
+                    $crate::memoizer::MemoTable::lookup(&*memo_table.borrow(), &memo_key);
+                if let Some(cached) = cached {
+                    return cached;
+                }
+
+                // Shadow the function so recursive calls go through the table.
+                // The table is only borrowed to look up and store, never across
+                // a call, so calls can nest
+                #[allow(unused_variables)]
+                let $name = |$($arg: $ty),*| memoized(memo_table, $($arg),*);
+                // Called through a closure so an early `return` still gets stored
+                #[allow(clippy::redundant_closure_call)]
+                let result = (|| -> $ret { $body })();
+
+                $crate::memoizer::MemoTable::store(
+                    &mut *memo_table.borrow_mut(),
+                    memo_key,
+                    result.clone(),
+                );
+                result
+            }
+
+            let memo_table = ::std::cell::RefCell::new($table);
+            memoized(&memo_table, $($arg),*)
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty
+        where key: $key_ty:ty = $key:tt
+        $body:block
+    ) => {
+        $crate::memoized! {
+            @impl
+            $(#[$meta])*
+            $vis fn $name($($arg: $ty),*) -> $ret;
+            table: $crate::memoizer::Memoizer<$key_ty, $ret, $crate::fx_hash::FxBuildHasher> =
+                $crate::memoizer::Memoizer::with_hasher($crate::fx_hash::FxBuildHasher::default());
+            key: $key_ty = $key;
+            $body
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty
+        where dense key: [usize; $n:literal] = $key:tt, bounds = $bounds:tt
+        $body:block
+    ) => {
+        $crate::memoized! {
+            @impl
+            $(#[$meta])*
+            $vis fn $name($($arg: $ty),*) -> $ret;
+            table: $crate::memoizer::DenseMemo<$ret, $n> = $crate::memoizer::DenseMemo::new($bounds);
+            key: [usize; $n] = $key;
+            $body
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty
+        $body:block
+    ) => {
+        $crate::memoized! {
+            $(#[$meta])*
+            $vis fn $name($($arg: $ty),*) -> $ret
+            where key: ($($ty,)*) = ($(::std::clone::Clone::clone(&$arg),)*)
+            $body
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    memoized! {
+        fn ackermann(m: u64, n: u64) -> u64 {
+            match (m, n) {
+                (0, n) => n + 1,
+                (m, 0) => ackermann(m - 1, 1),
+                (m, n) => ackermann(m - 1, ackermann(m, n - 1)),
+            }
+        }
+    }
+
+    memoized! {
+        fn paths(grid: &[Vec<bool>], x: usize, y: usize) -> u64
+        where dense key: [usize; 2] = [x, y], bounds = [grid[0].len(), grid.len()]
+        {
+            if !grid[y][x] {
+                return 0;
+            }
+            if x == 0 && y == 0 {
+                return 1;
+            }
+            let from_left = if x > 0 { paths(grid, x - 1, y) } else { 0 };
+            let from_above = if y > 0 { paths(grid, x, y - 1) } else { 0 };
+            from_left + from_above
+        }
+    }
+
+    #[test]
+    fn nested_recursive_calls() {
+        assert_eq!(ackermann(2, 3), 9);
+        assert_eq!(ackermann(3, 3), 61);
+    }
+
+    #[test]
+    fn dense_key() {
+        let open = vec![vec![true; 17]; 17];
+        assert_eq!(paths(&open, 16, 16), 601080390); // 32 choose 16
+
+        let mut blocked = open.clone();
+        blocked[1][1] = false;
+        assert_eq!(paths(&blocked, 1, 1), 0);
+        assert_eq!(paths(&blocked, 2, 2), 2);
+    }
+
+    #[test]
+    fn dense_memo_allocates_lazily() {
+        let mut memo: DenseMemo<u64, 2> = DenseMemo::new([3, 4]);
+        assert_eq!(memo.get([2, 3]), None);
+        assert!(memo.table.is_empty());
+        memo.insert([2, 3], 7);
+        assert_eq!(memo.table.len(), 12);
+        assert_eq!(memo.get([2, 3]), Some(&7));
+        assert_eq!(memo.get([0, 0]), None);
+    }
+
+    #[test]
+    fn dense_memo_out_of_bounds() {
+        let mut memo: DenseMemo<u64, 2> = DenseMemo::new([3, 4]);
+        memo.insert([0, 0], 1);
+        assert_eq!(memo.get([3, 0]), None);
+        assert_eq!(memo.get([0, 4]), None);
+        // Would be in bounds as a flat index
+        assert_eq!(memo.get([0, 5]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn dense_memo_insert_out_of_bounds() {
+        let mut memo: DenseMemo<u64, 2> = DenseMemo::new([3, 4]);
+        memo.insert([1, 4], 1);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("memo_test_{}", std::process::id()));
+        let path = dir.join("table.memo");
+
+        let mut memo: Memoizer<(u64, u8), u64> = Memoizer::new();
+        memo.insert((3, 1), 30);
+        memo.insert((u64::MAX, 0), 0);
+        memo.save(&path, 42).unwrap();
+
+        let loaded: Memoizer<(u64, u8), u64> = Memoizer::load(&path, 42).unwrap().unwrap();
+        assert_eq!(loaded.get(&(3, 1)), Some(&30));
+        assert_eq!(loaded.get(&(u64::MAX, 0)), Some(&0));
+        assert!(
+            Memoizer::<(u64, u8), u64>::load(&path, 43)
+                .unwrap()
+                .is_none()
+        );
+
+        remove_stale_memos(&dir, 43).unwrap();
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let dir = std::env::temp_dir().join(format!("memo_string_test_{}", std::process::id()));
+        let path = dir.join("table.memo");
+
+        let awkward = [
+            "",
+            "plain",
+            "two words",
+            "tab\tline\nreturn\r",
+            "back\\s\\",
+            " \\",
+        ];
+        let mut memo: Memoizer<(String, u8), String> = Memoizer::new();
+        for (i, text) in awkward.iter().enumerate() {
+            memo.insert((text.to_string(), i as u8), text.to_string());
+        }
+        memo.save(&path, 7).unwrap();
+        assert!(!dir.join("table.memo.tmp").exists());
+
+        let loaded: Memoizer<(String, u8), String> = Memoizer::load(&path, 7).unwrap().unwrap();
+        for (i, text) in awkward.iter().enumerate() {
+            assert_eq!(
+                loaded.get(&(text.to_string(), i as u8)),
+                Some(&text.to_string())
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!("a b\\".to_string().serialize(), "a\\sb\\\\");
+        assert_eq!(String::deserialize("a\\x"), None);
+        assert_eq!(String::deserialize("trailing\\"), None);
+    }
+}
@@ -0,0 +1,26 @@
+/*
+    Deterministic random numbers for the randomized tests,
+    so every run checks the same cases
+*/
+
+/// Xorshift64. Fast and seedable, nowhere near good enough for anything but tests
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "Xorshift gets stuck at 0");
+        XorShift(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// In 0..bound, slightly biased for bounds that don't divide 2^64
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}